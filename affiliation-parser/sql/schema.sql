@@ -0,0 +1,104 @@
+CREATE TABLE IF NOT EXISTS records (
+    record_id       VARCHAR PRIMARY KEY,
+    primary_id      VARCHAR NOT NULL,
+    primary_id_type VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS alternate_identifiers (
+    alternate_identifier_id VARCHAR PRIMARY KEY,
+    record_id               VARCHAR NOT NULL,
+    identifier_type         VARCHAR NOT NULL,
+    identifier_value        VARCHAR NOT NULL,
+    process_id              VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS record_attributes (
+    record_attribute_id VARCHAR PRIMARY KEY,
+    record_id           VARCHAR NOT NULL,
+    attribute_name      VARCHAR NOT NULL,
+    attribute_value     VARCHAR NOT NULL,
+    process_id          VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS values (
+    value_id        VARCHAR PRIMARY KEY,
+    value_type      VARCHAR NOT NULL,
+    value_content   VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS process_record_relationships (
+    process_record_id VARCHAR PRIMARY KEY,
+    process_id        VARCHAR NOT NULL,
+    record_id         VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    timestamp         TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS process_value_relationships (
+    process_value_id  VARCHAR PRIMARY KEY,
+    process_id        VARCHAR NOT NULL,
+    value_id          VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    confidence_score  FLOAT,
+    timestamp         TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS record_value_relationships (
+    record_value_id   VARCHAR PRIMARY KEY,
+    record_id         VARCHAR NOT NULL,
+    value_id          VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    ordinal           INTEGER NOT NULL,
+    process_id        VARCHAR NOT NULL,
+    timestamp         TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS value_value_relationships (
+    value_value_id    VARCHAR PRIMARY KEY,
+    source_value_id   VARCHAR NOT NULL,
+    target_value_id   VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    ordinal           INTEGER,
+    process_id        VARCHAR NOT NULL,
+    confidence_score  FLOAT,
+    timestamp         TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS record_record_relationships (
+    record_record_id  VARCHAR PRIMARY KEY,
+    source_record_id  VARCHAR NOT NULL,
+    target_record_id  VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    ordinal           INTEGER NOT NULL,
+    process_id        VARCHAR NOT NULL,
+    timestamp         TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS relationship_provenance (
+    relationship_id    VARCHAR NOT NULL,
+    relationship_table VARCHAR NOT NULL,
+    input_file         VARCHAR NOT NULL,
+    line_number        BIGINT NOT NULL,
+    json_pointer       VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sources (
+    source_id           VARCHAR PRIMARY KEY,
+    source_name         VARCHAR,
+    source_description  VARCHAR
+);
+
+CREATE TABLE IF NOT EXISTS processes (
+    process_id           VARCHAR PRIMARY KEY,
+    process_name         VARCHAR,
+    process_description  VARCHAR
+);
+
+CREATE TABLE IF NOT EXISTS source_process_relationships (
+    source_process_id VARCHAR PRIMARY KEY,
+    source_id         VARCHAR NOT NULL,
+    process_id        VARCHAR NOT NULL,
+    relationship_type VARCHAR NOT NULL,
+    start_date        DATE NOT NULL,
+    end_date          DATE
+);
@@ -1,36 +1,39 @@
 use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, Date32Array, Float32Array, Int32Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
 use chrono::{SecondsFormat, Utc};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use csv::Writer;
 use crossbeam_channel::{bounded, Receiver, Sender};
-use dashmap::{DashMap, DashSet};
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use bzip2::read::MultiBzDecoder;
 use flate2::read::GzDecoder;
-use glob::glob;
-use hex;
+use glob::{glob, MatchOptions, Pattern};
 use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, error, info, warn, LevelFilter};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
 use rayon::prelude::*;
+use regex::Regex;
+use serde::de::{DeserializeSeed, Deserializer, Error as DeError, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde::Deserialize;
 use serde_json::Value;
-use serde_yaml;
 use sha2::{Digest, Sha256};
 use simple_logger::SimpleLogger;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
 use std::time::{Duration, Instant};
 use time::macros::format_description;
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 
-#[cfg(target_os = "linux")]
-use std::fs::read_to_string;
-#[cfg(target_os = "windows")]
-use std::process::Command as WinCommand;
-
 mod memory_usage {
     use log::info;
     #[cfg(target_os = "linux")]
@@ -43,7 +46,8 @@ mod memory_usage {
     pub fn get_memory_usage() -> Option<MemoryStats> {
         let pid = std::process::id(); let status_file = format!("/proc/{}/status", pid); let content = read_to_string(status_file).ok()?;
         let mut vm_rss_kb = None; let mut vm_size_kb = None;
-        for line in content.lines() { if line.starts_with("VmRSS:") { vm_rss_kb = line.split_whitespace().nth(1).and_then(|s| s.parse::<f64>().ok()); } else if line.starts_with("VmSize:") { vm_size_kb = line.split_whitespace().nth(1).and_then(|s| s.parse::<f64>().ok()); } if vm_rss_kb.is_some() && vm_size_kb.is_some() { break; } }
+        for line in content.lines() { if line.starts_with("VmRSS:") { vm_rss_kb = line.split_whitespace().nth(1).and_then(|s| s.parse::<f64>().ok()); } else if line.starts_with("VmSize:") { vm_size_kb = line.split_whitespace().nth(1).and_then(|s| s.parse::<f64>().ok()); }
+            if vm_rss_kb.is_some() && vm_size_kb.is_some() { break; } }
         let rss_mb = vm_rss_kb? / 1024.0; let vm_size_mb = vm_size_kb? / 1024.0; let mut percent = None;
         if let Ok(meminfo) = read_to_string("/proc/meminfo") { if let Some(mem_total_kb) = meminfo.lines().find(|line| line.starts_with("MemTotal:")).and_then(|line| line.split_whitespace().nth(1)).and_then(|s| s.parse::<f64>().ok()) { if mem_total_kb > 0.0 { percent = Some((vm_rss_kb? / mem_total_kb) * 100.0); } } }
         Some(MemoryStats { rss_mb, vm_size_mb, percent })
@@ -86,7 +90,18 @@ struct TaskConfig {
     profile: PathBuf,
     input_dir: PathBuf,
     #[serde(default)]
-    filters: HashMap<String, String>,
+    file_patterns: Option<Vec<String>>,
+    #[serde(default)]
+    filters: serde_yaml::Mapping,
+}
+
+fn default_file_patterns(profile: &Profile) -> Vec<String> {
+    let patterns: &[&str] = if profile.record_array_path.is_some() {
+        &["**/*.json", "**/*.json.gz", "**/*.json.zst", "**/*.json.bz2"]
+    } else {
+        &["**/*.jsonl", "**/*.jsonl.gz", "**/*.jsonl.zst", "**/*.jsonl.bz2"]
+    };
+    patterns.iter().map(|p| p.to_string()).collect()
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -94,13 +109,89 @@ struct Profile {
     profile_description: String,
     source_info: SourceInfo,
     process_info: ProcessInfo,
+    #[serde(default)]
+    record_array_path: Option<String>,
     record_identifier: RecordIdentifierConfig,
     deterministic_ids: DeterministicIdConfig,
     null_values: HashMap<String, NullValueConfig>,
     filters: Option<Vec<FilterConfig>>,
+    #[serde(default)]
+    record_attributes: Vec<RecordAttributeConfig>,
+    #[serde(default)]
+    record_links: Vec<RecordLinkConfig>,
     entities: Vec<EntityConfig>,
 }
 
+/// A record-level field such as title or publication year, written as key/value rows to the
+/// `record_attributes` table.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RecordAttributeConfig {
+    name: String,
+    path: JsonPath,
+    #[serde(default, deserialize_with = "deserialize_transforms")]
+    transforms: Vec<ValueTransform>,
+}
+
+/// A field holding identifiers of other works (references, relations, updates), written as
+/// edges to the `record_record_relationships` table.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RecordLinkConfig {
+    relationship_type: String,
+    path: JsonPath,
+    #[serde(rename = "type", default = "default_record_identifier_type")]
+    id_type: String,
+    #[serde(default)]
+    normalize: Option<IdentifierKind>,
+}
+
+impl Profile {
+    /// Checks the transforms, extractions and filter conditions in the profile so mistakes fail at
+    /// load time. Paths are already compiled when the profile is parsed.
+    fn validate(&self) -> Result<()> {
+        for filter in self.filters.iter().flatten() {
+            for transform in &filter.transform {
+                transform.validate().with_context(|| format!("Invalid transform for filter '{}'", filter.cli_arg))?;
+            }
+            // Profiles written before filter transforms existed relied on this split happening
+            // implicitly; without it every record would silently fail the filter.
+            let from_doi = matches!(filter.fallback_from.as_ref().map(|p| p.source.as_str()), Some("/DOI") | Some("DOI"));
+            if filter.cli_arg == "doi_prefix" && from_doi && filter.transform.is_empty() {
+                return Err(anyhow::anyhow!("Filter 'doi_prefix' falls back to the whole DOI without a transform, so no prefix would ever match. Add \"transform\": {{\"type\": \"split_before\", \"delimiter\": \"/\"}} to the filter"));
+            }
+        }
+        for attribute in &self.record_attributes {
+            for transform in &attribute.transforms {
+                transform.validate().with_context(|| format!("Invalid transform for record attribute '{}'", attribute.name))?;
+            }
+        }
+        fn validate_entities(entities: &[EntityConfig]) -> Result<()> {
+            for entity in entities {
+                if let Some(extraction) = &entity.value_extraction {
+                    extraction.validate().with_context(|| format!("Invalid value_extraction for entity '{}'", entity.name))?;
+                }
+                for related in entity.related_values.iter().flatten() {
+                    if let Some(condition) = &related.filter_condition {
+                        condition.validate(false).with_context(|| format!("Invalid filter_condition for related value '{}'", related.name))?;
+                    }
+                    related.extract_value.validate().with_context(|| format!("Invalid extract_value for related value '{}'", related.name))?;
+                    if related.extract_value.options.split.is_some() {
+                        return Err(anyhow::anyhow!("Related value '{}': 'split' is only supported in entity value_extraction", related.name));
+                    }
+                }
+                for join in entity.lookup_joins.iter().flatten() {
+                    join.extract_value.validate().with_context(|| format!("Invalid extract_value for lookup join '{}'", join.name))?;
+                    if join.extract_value.options.split.is_some() {
+                        return Err(anyhow::anyhow!("Lookup join '{}': 'split' is only supported in entity value_extraction", join.name));
+                    }
+                }
+                validate_entities(entity.nested_entities.as_deref().unwrap_or_default())?;
+            }
+            Ok(())
+        }
+        validate_entities(&self.entities)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 struct SourceInfo {
     source_id: String,
@@ -117,15 +208,272 @@ struct ProcessInfo {
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct RecordIdentifierConfig {
-    path: String,
+    path: JsonPath,
+    #[serde(rename = "type", default = "default_record_identifier_type")]
+    id_type: String,
     required: bool,
+    #[serde(default)]
+    normalize: Option<IdentifierKind>,
+    /// Tried in order when `path` is missing or invalid; every one found is also written as an
+    /// alternate identifier.
+    #[serde(default)]
+    fallbacks: Vec<IdentifierSourceConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct IdentifierSourceConfig {
+    path: JsonPath,
+    #[serde(rename = "type")]
+    id_type: String,
+    #[serde(default)]
+    normalize: Option<IdentifierKind>,
+}
+
+fn default_record_identifier_type() -> String {
+    "doi".to_string()
+}
+
+impl RecordIdentifierConfig {
+    /// `(path, type, normalize)` for the primary identifier, then each fallback.
+    fn sources(&self) -> impl Iterator<Item = (&JsonPath, &str, Option<IdentifierKind>)> {
+        std::iter::once((&self.path, self.id_type.as_str(), self.normalize))
+            .chain(self.fallbacks.iter().map(|f| (&f.path, f.id_type.as_str(), f.normalize)))
+    }
+}
+
+/// Content hashed into the record ID. DOIs hash bare, as before fallbacks existed, so records
+/// keep their IDs; other types are prefixed so that e.g. a PMID cannot collide with another
+/// scheme's value.
+fn record_id_hashing_content(id_type: &str, value: &str) -> String {
+    if id_type == "doi" { value.to_string() } else { format!("{}:{}", id_type, value) }
+}
+
+/// Identifier schemes with a canonical form. Normalizing maps the variants seen across sources
+/// (resolver URLs, prefixes, case) onto that form and rejects strings that are not valid ids.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum IdentifierKind {
+    Doi,
+    Ror,
+    Orcid,
+    Pmid,
+    Pmcid,
+    Openalex,
+}
+
+impl IdentifierKind {
+    fn normalize(&self, raw: &str) -> Option<String> {
+        match self {
+            IdentifierKind::Doi => normalize_doi(raw),
+            IdentifierKind::Ror => normalize_ror(raw),
+            IdentifierKind::Orcid => normalize_orcid(raw),
+            IdentifierKind::Pmid => normalize_pmid(raw),
+            IdentifierKind::Pmcid => normalize_pmcid(raw),
+            IdentifierKind::Openalex => normalize_openalex_id(raw),
+        }
+    }
+}
+
+/// `https://doi.org/10.1006/ABC`, `doi:10.1006/abc` and `10.1006/abc` all become `10.1006/abc`.
+fn normalize_doi(raw: &str) -> Option<String> {
+    const RESOLVER_PREFIXES: [&str; 6] = ["https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "http://dx.doi.org/", "doi.org/", "dx.doi.org/"];
+    let trimmed = raw.trim();
+    let lowered = trimmed.to_lowercase();
+    let without_resolver = RESOLVER_PREFIXES.iter()
+        .find_map(|prefix| lowered.strip_prefix(prefix))
+        .or_else(|| lowered.strip_prefix("doi:").map(str::trim_start))
+        .unwrap_or(&lowered);
+    let (registrant, suffix) = without_resolver.split_once('/')?;
+    let registrant_code = registrant.strip_prefix("10.")?;
+    let valid_registrant = !registrant_code.is_empty() && registrant_code.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    let valid_suffix = !suffix.is_empty() && !suffix.chars().any(char::is_whitespace);
+    (valid_registrant && valid_suffix).then(|| without_resolver.to_string())
+}
+
+/// Lowercases `raw` and strips the first matching resolver prefix and any trailing `/`.
+fn strip_identifier_prefix(raw: &str, prefixes: &[&str]) -> String {
+    let lowered = raw.trim().to_lowercase();
+    prefixes.iter()
+        .find_map(|prefix| lowered.strip_prefix(prefix))
+        .unwrap_or(&lowered)
+        .trim_end_matches('/')
+        .trim()
+        .to_string()
+}
+
+/// `https://pubmed.ncbi.nlm.nih.gov/1000`, `pmid:1000` and `1000` all become `1000`.
+fn normalize_pmid(raw: &str) -> Option<String> {
+    let id = strip_identifier_prefix(raw, &["https://pubmed.ncbi.nlm.nih.gov/", "http://pubmed.ncbi.nlm.nih.gov/", "https://www.ncbi.nlm.nih.gov/pubmed/", "pmid:"]);
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then_some(id)
+}
+
+/// `https://www.ncbi.nlm.nih.gov/pmc/articles/PMC123`, `pmc123` and `123` all become `PMC123`.
+fn normalize_pmcid(raw: &str) -> Option<String> {
+    let id = strip_identifier_prefix(raw, &["https://www.ncbi.nlm.nih.gov/pmc/articles/", "http://www.ncbi.nlm.nih.gov/pmc/articles/", "https://pmc.ncbi.nlm.nih.gov/articles/", "pmcid:"]);
+    let digits = id.strip_prefix("pmc").unwrap_or(&id);
+    (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())).then(|| format!("PMC{}", digits))
+}
+
+/// `https://openalex.org/W2741809807` and `w2741809807` both become `W2741809807`.
+fn normalize_openalex_id(raw: &str) -> Option<String> {
+    let id = strip_identifier_prefix(raw, &["https://openalex.org/", "http://openalex.org/", "openalex.org/"]).to_uppercase();
+    let (entity, number) = id.split_at_checked(1)?;
+    let valid = entity.chars().all(|c| c.is_ascii_alphabetic()) && !number.is_empty() && number.chars().all(|c| c.is_ascii_digit());
+    valid.then_some(id)
+}
+
+/// `https://ror.org/05dxps055`, `ror.org/05dxps055` and `05dxps055` all become
+/// `https://ror.org/05dxps055`. The last two digits must match the ISO 7064 Mod 97-10 checksum
+/// of the six Crockford base32 characters after the leading `0`.
+fn normalize_ror(raw: &str) -> Option<String> {
+    const RESOLVER_PREFIXES: [&str; 4] = ["https://ror.org/", "http://ror.org/", "https://www.ror.org/", "ror.org/"];
+    const CROCKFORD_BASE32: &str = "0123456789abcdefghjkmnpqrstvwxyz";
+    let lowered = raw.trim().to_lowercase();
+    let id = RESOLVER_PREFIXES.iter()
+        .find_map(|prefix| lowered.strip_prefix(prefix))
+        .unwrap_or(&lowered)
+        .trim_end_matches('/');
+    if id.len() != 9 || !id.is_ascii() || !id.starts_with('0') { return None; }
+    let (body, check_digits) = id[1..].split_at(6);
+    let number = body.chars().try_fold(0u64, |acc, c| CROCKFORD_BASE32.find(c).map(|digit| acc * 32 + digit as u64))?;
+    let expected = 98 - (number * 100) % 97;
+    (check_digits.parse::<u64>().ok() == Some(expected) && check_digits.chars().all(|c| c.is_ascii_digit()))
+        .then(|| format!("https://ror.org/{}", id))
+}
+
+/// `http://orcid.org/0000-0002-1825-0097`, `0000-0002-1825-0097` and `0000000218250097` all
+/// become `https://orcid.org/0000-0002-1825-0097`. The last character must match the ISO 7064
+/// MOD 11-2 check digit of the first fifteen.
+fn normalize_orcid(raw: &str) -> Option<String> {
+    const RESOLVER_PREFIXES: [&str; 4] = ["https://orcid.org/", "http://orcid.org/", "https://www.orcid.org/", "orcid.org/"];
+    let lowered = raw.trim().to_lowercase();
+    let id = RESOLVER_PREFIXES.iter()
+        .find_map(|prefix| lowered.strip_prefix(prefix))
+        .unwrap_or(&lowered)
+        .trim_end_matches('/');
+    if !id.is_ascii() { return None; }
+    let compact: String = id.chars().filter(|c| *c != '-').collect::<String>().to_uppercase();
+    let hyphens_ok = id.len() == compact.len() || (id.len() == 19 && id.char_indices().all(|(i, c)| (c == '-') == (i % 5 == 4)));
+    if compact.len() != 16 || !hyphens_ok { return None; }
+    let total = compact[..15].chars().try_fold(0u32, |acc, c| c.to_digit(10).map(|d| (acc + d) * 2))?;
+    let expected = match (12 - total % 11) % 11 { 10 => 'X', d => char::from_digit(d, 10)? };
+    compact.ends_with(expected)
+        .then(|| format!("https://orcid.org/{}-{}-{}-{}", &compact[0..4], &compact[4..8], &compact[8..12], &compact[12..16]))
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct DeterministicIdConfig {
     record_prefix: String,
     value_prefix: String,
-    value_format: String,
+    value_format: IdTemplate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum IdTemplatePart {
+    Literal(String),
+    ValueType,
+    ValueContent,
+    SourceId,
+    ProcessId,
+    RecordId,
+    RecordField(JsonPath),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+struct IdTemplate {
+    format: String,
+    parts: Vec<IdTemplatePart>,
+}
+
+struct IdTemplateVars<'a> {
+    value_type: &'a str,
+    value_content: &'a str,
+    source_id: &'a str,
+    process_id: &'a str,
+    record: Option<&'a RecordContext<'a>>,
+}
+
+impl TryFrom<String> for IdTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(format: String) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => { chars.next(); literal.push('{'); }
+                '}' if chars.peek() == Some(&'}') => { chars.next(); literal.push('}'); }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(anyhow::anyhow!("Unclosed placeholder '{{{}' in value_format '{}'", name, format)),
+                        }
+                    }
+                    if !literal.is_empty() { parts.push(IdTemplatePart::Literal(std::mem::take(&mut literal))); }
+                    let part = match name.trim() {
+                        "value_type" => IdTemplatePart::ValueType,
+                        "value_content" => IdTemplatePart::ValueContent,
+                        "source_id" => IdTemplatePart::SourceId,
+                        "process_id" => IdTemplatePart::ProcessId,
+                        "record_id" => IdTemplatePart::RecordId,
+                        other => match other.strip_prefix("record:") {
+                            Some(path) if !path.trim().is_empty() => IdTemplatePart::RecordField(JsonPath::try_from(path.trim().to_string())
+                                .with_context(|| format!("Invalid path in value_format '{}'", format))?),
+                            _ => return Err(anyhow::anyhow!("Unknown placeholder '{{{}}}' in value_format '{}'. Supported: value_type, value_content, source_id, process_id, record_id, record:<path>", other, format)),
+                        },
+                    };
+                    parts.push(part);
+                }
+                '}' => return Err(anyhow::anyhow!("Unmatched '}}' in value_format '{}' (use '}}}}' for a literal brace)", format)),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() { parts.push(IdTemplatePart::Literal(literal)); }
+
+        for (required, label) in [(IdTemplatePart::ValueType, "value_type"), (IdTemplatePart::ValueContent, "value_content")] {
+            if !parts.contains(&required) {
+                return Err(anyhow::anyhow!("value_format '{}' must include the {{{}}} placeholder", format, label));
+            }
+        }
+        Ok(Self { format, parts })
+    }
+}
+
+impl IdTemplate {
+    fn is_record_scoped(&self) -> bool {
+        self.parts.iter().any(|p| matches!(p, IdTemplatePart::RecordId | IdTemplatePart::RecordField(_)))
+    }
+
+    fn render(&self, vars: &IdTemplateVars) -> String {
+        let mut rendered = String::with_capacity(self.format.len() + vars.value_content.len());
+        for part in &self.parts {
+            match part {
+                IdTemplatePart::Literal(text) => rendered.push_str(text),
+                IdTemplatePart::ValueType => rendered.push_str(vars.value_type),
+                IdTemplatePart::ValueContent => rendered.push_str(vars.value_content),
+                IdTemplatePart::SourceId => rendered.push_str(vars.source_id),
+                IdTemplatePart::ProcessId => rendered.push_str(vars.process_id),
+                IdTemplatePart::RecordId => {
+                    if let Some(record) = vars.record { rendered.push_str(record.record_id); }
+                }
+                IdTemplatePart::RecordField(path) => {
+                    if let Some(field_value) = vars.record.and_then(|r| path.first(r.record_json)) {
+                        match field_value.as_str() {
+                            Some(text) => rendered.push_str(text.trim()),
+                            None if !field_value.is_null() => rendered.push_str(&field_value.to_string()),
+                            None => {}
+                        }
+                    }
+                }
+            }
+        }
+        rendered
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -137,14 +485,180 @@ struct NullValueConfig {
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct FilterConfig {
     cli_arg: String,
-    path: String,
-    fallback_from: Option<String>,
+    path: JsonPath,
+    fallback_from: Option<JsonPath>,
+    #[serde(default, deserialize_with = "deserialize_transforms")]
+    transform: Vec<ValueTransform>,
+}
+
+/// A step that derives a new value from a matched JSON value, e.g. the prefix of a DOI, the
+/// year of a date or a cleaned-up affiliation string. Steps run in order and a step that cannot
+/// apply (or leaves an empty string) drops the value.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ValueTransform {
+    SplitBefore { delimiter: String },
+    SplitAfter { delimiter: String },
+    RegexCapture {
+        pattern: ConfigRegex,
+        #[serde(default = "default_capture_group")]
+        group: usize,
+    },
+    RegexReplace {
+        pattern: ConfigRegex,
+        #[serde(default)]
+        replacement: String,
+    },
+    Lowercase,
+    Uppercase,
+    Trim,
+    CollapseWhitespace,
+    StripHtml,
+    StripPunctuation,
+    UnicodeNfkc,
+    YearFromDateParts,
+}
+
+fn default_capture_group() -> usize {
+    1
+}
+
+fn deserialize_transforms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ValueTransform>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ValueTransform),
+        Many(Vec<ValueTransform>),
+    }
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(transform)) => vec![transform],
+        Some(OneOrMany::Many(transforms)) => transforms,
+        None => Vec::new(),
+    })
+}
+
+impl ValueTransform {
+    fn validate(&self) -> Result<()> {
+        if let ValueTransform::RegexCapture { pattern, group } = self {
+            if *group >= pattern.0.captures_len() {
+                return Err(anyhow::anyhow!("regex_capture group {} does not exist in pattern '{}'", group, pattern.0.as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, value: &Value) -> Option<String> {
+        if let ValueTransform::YearFromDateParts = self {
+            return year_from_date_value(value);
+        }
+        let text = value.as_str().map(Cow::Borrowed).or_else(|| (value.is_number() || value.is_boolean()).then(|| Cow::Owned(value.to_string())))?;
+        self.apply_text(&text)
+    }
+
+    fn apply_text(&self, text: &str) -> Option<String> {
+        let transformed = match self {
+            ValueTransform::SplitBefore { delimiter } => text.split_once(delimiter.as_str()).map(|(before, _)| before.to_string()),
+            ValueTransform::SplitAfter { delimiter } => text.split_once(delimiter.as_str()).map(|(_, after)| after.to_string()),
+            ValueTransform::RegexCapture { pattern, group } => pattern.0.captures(text).and_then(|c| c.get(*group)).map(|m| m.as_str().to_string()),
+            ValueTransform::RegexReplace { pattern, replacement } => Some(pattern.0.replace_all(text, replacement.as_str()).into_owned()),
+            ValueTransform::Lowercase => Some(text.to_lowercase()),
+            ValueTransform::Uppercase => Some(text.to_uppercase()),
+            ValueTransform::Trim => Some(text.trim().to_string()),
+            ValueTransform::CollapseWhitespace => Some(text.split_whitespace().collect::<Vec<_>>().join(" ")),
+            ValueTransform::StripHtml => {
+                let without_tags = html_tag_regex().replace_all(text, "");
+                Some(html_escape::decode_html_entities(&without_tags).into_owned())
+            }
+            ValueTransform::StripPunctuation => Some(text.trim_matches(|c: char| c.is_whitespace() || is_strippable_punctuation(c)).to_string()),
+            ValueTransform::UnicodeNfkc => Some(text.nfkc().collect()),
+            ValueTransform::YearFromDateParts => year_from_date_value(&Value::String(text.to_string())),
+        };
+        transformed.filter(|t| !t.is_empty())
+    }
+}
+
+fn html_tag_regex() -> &'static Regex {
+    static HTML_TAG: OnceLock<Regex> = OnceLock::new();
+    HTML_TAG.get_or_init(|| Regex::new(r"<[^<>]*>").expect("valid HTML tag regex"))
+}
+
+/// Punctuation that `strip_punctuation` removes from the ends of a string. Brackets and quotes
+/// are kept so that e.g. "University (UK)" is left intact.
+fn is_strippable_punctuation(c: char) -> bool {
+    (c.is_ascii_punctuation() && !matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | '"' | '\'')) || matches!(c, '\u{2010}'..='\u{2015}' | '\u{2022}' | '\u{2026}' | '\u{00B7}')
+}
+
+/// Year from a Crossref `date-parts` array (`[[2020, 5, 1]]` or `[2020, 5, 1]`), a bare year,
+/// or a date string starting with a four-digit year (`2020-05-01`).
+fn year_from_date_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(parts) => parts.first().and_then(year_from_date_value),
+        Value::Number(year) => year.as_i64().map(|y| y.to_string()),
+        Value::String(date) => {
+            let year = date.trim().get(..4)?;
+            year.chars().all(|c| c.is_ascii_digit()).then(|| year.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn apply_transforms(transforms: &[ValueTransform], value: &Value) -> Option<String> {
+    let (first, rest) = transforms.split_first()?;
+    apply_text_transforms(rest, first.apply(value)?)
+}
+
+fn apply_text_transforms(transforms: &[ValueTransform], text: String) -> Option<String> {
+    transforms.iter().try_fold(text, |current, transform| transform.apply_text(&current))
+}
+
+/// A task's record filter: profile filter keys mapped to conditions, combined with AND, with
+/// `all_of`/`any_of`/`not` for nesting. Built from the run config by `resolve_task_filters`.
+#[derive(Debug, Clone, PartialEq)]
+enum RecordFilter {
+    Field { filter: FilterConfig, condition: Box<FilterConditionConfig> },
+    All(Vec<RecordFilter>),
+    Any(Vec<RecordFilter>),
+    Not(Box<RecordFilter>),
+}
+
+impl RecordFilter {
+    fn is_empty(&self) -> bool {
+        matches!(self, RecordFilter::All(filters) if filters.is_empty())
+    }
+
+    fn matches(&self, record: &Value) -> bool {
+        match self {
+            RecordFilter::Field { filter, condition } => condition.matches_texts(record, None, &filter.record_values(record)),
+            RecordFilter::All(filters) => filters.iter().all(|f| f.matches(record)),
+            RecordFilter::Any(filters) => filters.iter().any(|f| f.matches(record)),
+            RecordFilter::Not(filter) => !filter.matches(record),
+        }
+    }
+}
+
+impl FilterConfig {
+    /// The record's values at `path`, or when there are none, those at `fallback_from` run
+    /// through `transform`.
+    fn record_values(&self, record: &Value) -> Vec<String> {
+        let current_values = self.path.texts(record);
+        if !current_values.is_empty() {
+            return current_values;
+        }
+
+        match &self.fallback_from {
+            Some(fallback_path) if self.transform.is_empty() => fallback_path.texts(record),
+            Some(fallback_path) => fallback_path.values(record).into_iter()
+                .filter_map(|v| apply_transforms(&self.transform, v))
+                .collect(),
+            None => current_values,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct EntityConfig {
     name: String,
-    path: String,
+    path: JsonPath,
     is_array: bool,
     relationship_to_record: Option<String>,
     relationship_to_parent: Option<String>,
@@ -155,12 +669,47 @@ struct EntityConfig {
     related_values: Option<Vec<RelatedValueConfig>>,
     #[serde(default)]
     lookup_joins: Option<Vec<LookupJoinConfig>>,
+    #[serde(default)]
+    identity_scope: IdentityScope,
+}
+
+/// What an entity value is unique within. `global` values are shared by every record with the
+/// same string; `record` and `parent` values are distinct per record or per parent value, and
+/// `occurrence` values per position of the entity within its parent (or record). Scoped values
+/// link to the shared value with `same_string_as`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum IdentityScope {
+    #[default]
+    Global,
+    Record,
+    Parent,
+    Occurrence,
+}
+
+const SAME_STRING_AS_RELATIONSHIP: &str = "same_string_as";
+
+impl IdentityScope {
+    /// Folded into the value ID hash. Top-level entities have no parent value, so `parent` and
+    /// `occurrence` scope them to the record.
+    fn scope_key(&self, record_id: &str, parent_value_id: Option<&str>, entity_name: &str, ordinal: i32) -> Option<String> {
+        let within = match (self, parent_value_id) {
+            (IdentityScope::Global, _) => return None,
+            (IdentityScope::Record, _) => return Some(format!("record:{}", record_id)),
+            (IdentityScope::Parent | IdentityScope::Occurrence, Some(parent_id)) => format!("parent:{}", parent_id),
+            (IdentityScope::Parent | IdentityScope::Occurrence, None) => format!("record:{}", record_id),
+        };
+        match self {
+            IdentityScope::Occurrence => Some(format!("{}|{}#{}", within, entity_name, ordinal)),
+            _ => Some(within),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct RelatedValueConfig {
     name: String,
-    path: String,
+    path: JsonPath,
     is_array: bool,
     filter_condition: Option<FilterConditionConfig>,
     extract_value: ValueExtractionConfig,
@@ -170,41 +719,426 @@ struct RelatedValueConfig {
     take_first_match: Option<bool>,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ValueExtractionConfig {
+    #[serde(flatten)]
+    source: ExtractionSource,
+    #[serde(flatten)]
+    options: ExtractionOptions,
+}
+
+/// Where an extraction reads its string from, selected by `type`.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
-enum ValueExtractionConfig {
+enum ExtractionSource {
     #[serde(rename = "field")]
-    Field {
-        field: String,
-        target_value_type: String,
-        use_null: Option<String>,
-        #[serde(default)]
-        confidence_score: Option<f32>,
-    },
+    Field { field: FieldPath },
     #[serde(rename = "combine_fields")]
-    CombineFields {
-        fields: Vec<String>,
-        separator: String,
-        target_value_type: String,
-        use_null: Option<String>,
-        #[serde(default)]
-        confidence_score: Option<f32>,
+    CombineFields { fields: Vec<FieldPath>, separator: String },
+    /// The node itself, for arrays of plain strings such as `ISSN` or `subject`.
+    #[serde(rename = "item", alias = "self")]
+    Item,
+    /// The first of `fields` with a non-empty value.
+    #[serde(rename = "coalesce")]
+    Coalesce { fields: Vec<FieldPath> },
+    #[serde(rename = "template")]
+    Template { template: ValueTemplate },
+}
+
+/// Options every extraction type takes.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ExtractionOptions {
+    target_value_type: String,
+    use_null: Option<String>,
+    #[serde(default)]
+    confidence_score: Option<f32>,
+    #[serde(default, deserialize_with = "deserialize_transforms")]
+    transforms: Vec<ValueTransform>,
+    #[serde(default)]
+    keep_raw: Option<KeepRawConfig>,
+    #[serde(default)]
+    normalize: Option<ValueNormalizeConfig>,
+    #[serde(default)]
+    split: Option<SplitConfig>,
+}
+
+/// A `{field}` template for building values from several fields. Text in `[...]` is optional and
+/// is only rendered when every field inside it is present; a missing field outside brackets makes
+/// the whole value missing. `{{`, `}}`, `[[` and `]]` are literal braces and brackets.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+struct ValueTemplate {
+    parts: Vec<ValueTemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ValueTemplatePart {
+    Literal(String),
+    Field(FieldPath),
+    Optional(Vec<ValueTemplatePart>),
+}
+
+impl TryFrom<String> for ValueTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(template: String) -> Result<Self> {
+        let mut stack: Vec<Vec<ValueTemplatePart>> = vec![Vec::new()];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        let flush = |literal: &mut String, stack: &mut Vec<Vec<ValueTemplatePart>>| {
+            if !literal.is_empty() { stack.last_mut().expect("template stack is never empty").push(ValueTemplatePart::Literal(std::mem::take(literal))); }
+        };
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' | '[' | ']' if chars.peek() == Some(&c) => { chars.next(); literal.push(c); }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(anyhow::anyhow!("Unclosed placeholder '{{{}' in template '{}'", name, template)),
+                        }
+                    }
+                    if name.trim().is_empty() { return Err(anyhow::anyhow!("Empty placeholder in template '{}'", template)); }
+                    flush(&mut literal, &mut stack);
+                    stack.last_mut().expect("template stack is never empty").push(ValueTemplatePart::Field(FieldPath::try_from(name.trim().to_string())
+                        .with_context(|| format!("Invalid field in template '{}'", template))?));
+                }
+                '[' => { flush(&mut literal, &mut stack); stack.push(Vec::new()); }
+                ']' if stack.len() > 1 => {
+                    flush(&mut literal, &mut stack);
+                    let section = stack.pop().expect("checked above");
+                    stack.last_mut().expect("template stack is never empty").push(ValueTemplatePart::Optional(section));
+                }
+                '}' | ']' => return Err(anyhow::anyhow!("Unmatched '{}' in template '{}' (use '{}{}' for a literal)", c, template, c, c)),
+                _ => literal.push(c),
+            }
+        }
+        if stack.len() > 1 { return Err(anyhow::anyhow!("Unclosed '[' in template '{}'", template)); }
+        flush(&mut literal, &mut stack);
+        let parts = stack.pop().expect("template stack is never empty");
+        let template = Self { parts };
+        if template.fields().is_empty() { return Err(anyhow::anyhow!("Template has no {{field}} placeholders")); }
+        Ok(template)
+    }
+}
+
+impl ValueTemplate {
+    fn fields(&self) -> Vec<&FieldPath> {
+        fn collect<'a>(parts: &'a [ValueTemplatePart], out: &mut Vec<&'a FieldPath>) {
+            for part in parts {
+                match part {
+                    ValueTemplatePart::Field(field) => out.push(field),
+                    ValueTemplatePart::Optional(inner) => collect(inner, out),
+                    ValueTemplatePart::Literal(_) => {}
+                }
+            }
+        }
+        let mut fields = Vec::new();
+        collect(&self.parts, &mut fields);
+        fields
+    }
+
+    /// `None` when a required field is missing or no field at all was filled in.
+    fn render(&self, node: &Value) -> Option<String> {
+        fn render_parts(parts: &[ValueTemplatePart], node: &Value, out: &mut String) -> Option<usize> {
+            let mut filled = 0;
+            for part in parts {
+                match part {
+                    ValueTemplatePart::Literal(text) => out.push_str(text),
+                    ValueTemplatePart::Field(field) => {
+                        out.push_str(&first_scalar_text(node, field)?);
+                        filled += 1;
+                    }
+                    ValueTemplatePart::Optional(inner) => {
+                        let mut section = String::new();
+                        if let Some(count) = render_parts(inner, node, &mut section) {
+                            out.push_str(&section);
+                            filled += count;
+                        }
+                    }
+                }
+            }
+            Some(filled)
+        }
+        let mut rendered = String::new();
+        let filled = render_parts(&self.parts, node, &mut rendered)?;
+        (filled > 0).then(|| rendered.trim().to_string()).filter(|s| !s.is_empty())
+    }
+}
+
+/// Splits one extracted string into several values, e.g. affiliations packed into one string
+/// with `;`. Each part then goes through `transforms` and `normalize` separately.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "SplitSpec")]
+struct SplitConfig {
+    separator: SplitSeparator,
+    trim: bool,
+    drop_empty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SplitSeparator {
+    Delimiter(String),
+    Regex(ConfigRegex),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SplitSpec {
+    Delimiter(String),
+    Full {
+        delimiter: Option<String>,
+        regex: Option<ConfigRegex>,
+        #[serde(default = "default_true")]
+        trim: bool,
+        #[serde(default = "default_true")]
+        drop_empty: bool,
     },
 }
 
+fn default_true() -> bool {
+    true
+}
+
+impl TryFrom<SplitSpec> for SplitConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: SplitSpec) -> Result<Self> {
+        let (separator, trim, drop_empty) = match spec {
+            SplitSpec::Delimiter(delimiter) => (SplitSeparator::Delimiter(delimiter), true, true),
+            SplitSpec::Full { delimiter: Some(delimiter), regex: None, trim, drop_empty } => (SplitSeparator::Delimiter(delimiter), trim, drop_empty),
+            SplitSpec::Full { delimiter: None, regex: Some(regex), trim, drop_empty } => (SplitSeparator::Regex(regex), trim, drop_empty),
+            SplitSpec::Full { .. } => return Err(anyhow::anyhow!("split needs exactly one of 'delimiter' or 'regex'")),
+        };
+        if matches!(&separator, SplitSeparator::Delimiter(d) if d.is_empty()) {
+            return Err(anyhow::anyhow!("split delimiter must not be empty"));
+        }
+        Ok(SplitConfig { separator, trim, drop_empty })
+    }
+}
+
+impl SplitConfig {
+    fn split(&self, text: &str) -> Vec<String> {
+        let parts: Vec<&str> = match &self.separator {
+            SplitSeparator::Delimiter(delimiter) => text.split(delimiter.as_str()).collect(),
+            SplitSeparator::Regex(regex) => regex.0.split(text).collect(),
+        };
+        parts.into_iter()
+            .map(|part| if self.trim { part.trim() } else { part })
+            .filter(|part| !self.drop_empty || !part.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Keeps the extracted string as it was before `transforms` as its own value, linked from the
+/// transformed value.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
+struct KeepRawConfig {
+    value_type: String,
+    relationship: String,
+}
+
+/// Normalizes the (transformed) value as an identifier before it is hashed. Strings that are
+/// not valid ids fall back to `use_null`, or with `on_invalid: "flag"` are kept as extracted
+/// at `invalid_confidence`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "ValueNormalizeSpec")]
+struct ValueNormalizeConfig {
+    identifier: IdentifierKind,
+    on_invalid: InvalidIdentifierPolicy,
+    invalid_confidence: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ValueNormalizeSpec {
+    Kind(IdentifierKind),
+    Full {
+        identifier: IdentifierKind,
+        #[serde(default)]
+        on_invalid: InvalidIdentifierPolicy,
+        #[serde(default = "default_invalid_identifier_confidence")]
+        invalid_confidence: f32,
+    },
+}
+
+impl From<ValueNormalizeSpec> for ValueNormalizeConfig {
+    fn from(spec: ValueNormalizeSpec) -> Self {
+        match spec {
+            ValueNormalizeSpec::Kind(identifier) => ValueNormalizeConfig { identifier, on_invalid: InvalidIdentifierPolicy::default(), invalid_confidence: default_invalid_identifier_confidence() },
+            ValueNormalizeSpec::Full { identifier, on_invalid, invalid_confidence } => ValueNormalizeConfig { identifier, on_invalid, invalid_confidence },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum InvalidIdentifierPolicy {
+    #[default]
+    UseNull,
+    Flag,
+}
+
+fn default_invalid_identifier_confidence() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
 struct FilterConditionConfig {
-    field: String,
-    equals: String,
+    field: Option<FieldPath>,
+    #[serde(default, deserialize_with = "deserialize_scalar_text")]
+    equals: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_scalar_text")]
+    not_equals: Option<String>,
+    #[serde(rename = "in", default, deserialize_with = "deserialize_scalar_text_list")]
+    in_list: Option<Vec<String>>,
+    regex: Option<ConfigRegex>,
+    exists: Option<bool>,
+    missing: Option<bool>,
+    gt: Option<FilterBound>,
+    gte: Option<FilterBound>,
+    lt: Option<FilterBound>,
+    lte: Option<FilterBound>,
+    between: Option<(FilterBound, FilterBound)>,
     case_insensitive: Option<bool>,
+    all_of: Option<Vec<FilterConditionConfig>>,
+    any_of: Option<Vec<FilterConditionConfig>>,
+    not: Option<Box<FilterConditionConfig>>,
+}
+
+/// Lets YAML/JSON numbers and booleans stand in for strings in comparisons (`equals: 2020`).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarText {
+    Text(String),
+    Number(serde_json::Number),
+    Bool(bool),
+}
+
+impl From<ScalarText> for String {
+    fn from(scalar: ScalarText) -> Self {
+        match scalar {
+            ScalarText::Text(text) => text,
+            ScalarText::Number(number) => number.to_string(),
+            ScalarText::Bool(flag) => flag.to_string(),
+        }
+    }
+}
+
+fn deserialize_scalar_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<ScalarText>::deserialize(deserializer)?.map(String::from))
+}
+
+fn deserialize_scalar_text_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    Ok(Option::<Vec<ScalarText>>::deserialize(deserializer)?.map(|items| items.into_iter().map(String::from).collect()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+struct ConfigRegex(Regex);
+
+impl TryFrom<String> for ConfigRegex {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, regex::Error> {
+        Regex::new(&pattern).map(ConfigRegex)
+    }
+}
+
+impl PartialEq for ConfigRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// A range bound: numbers compare numerically, strings (e.g. ISO dates) lexicographically.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum FilterBound {
+    Number(f64),
+    Text(String),
+}
+
+impl FilterBound {
+    fn compare(&self, value: &str) -> Option<std::cmp::Ordering> {
+        match self {
+            FilterBound::Number(bound) => value.trim().parse::<f64>().ok()?.partial_cmp(bound),
+            FilterBound::Text(bound) => Some(value.cmp(bound.as_str())),
+        }
+    }
+}
+
+impl FilterConditionConfig {
+    fn has_field_predicate(&self) -> bool {
+        self.equals.is_some() || self.not_equals.is_some() || self.in_list.is_some() || self.regex.is_some()
+            || self.exists.is_some() || self.missing.is_some()
+            || self.gt.is_some() || self.gte.is_some() || self.lt.is_some() || self.lte.is_some() || self.between.is_some()
+    }
+
+    fn children(&self) -> impl Iterator<Item = &FilterConditionConfig> {
+        self.all_of.iter().flatten().chain(self.any_of.iter().flatten()).chain(self.not.as_deref())
+    }
+
+    /// Nested conditions without their own `field` inherit the enclosing one.
+    fn validate(&self, has_inherited_field: bool) -> Result<()> {
+        let has_field = self.field.is_some() || has_inherited_field;
+        if !has_field && self.has_field_predicate() {
+            return Err(anyhow::anyhow!("Filter condition {:?} compares a value but has no 'field' (directly or from an enclosing condition)", self));
+        }
+        if !self.has_field_predicate() && self.children().next().is_none() {
+            return Err(anyhow::anyhow!("Filter condition {:?} has no predicate and no nested conditions, so it would match everything", self));
+        }
+        self.children().try_for_each(|child| child.validate(has_field))
+    }
+
+    fn matches(&self, node: &Value, inherited_field: Option<&FieldPath>) -> bool {
+        let field = self.field.as_ref().or(inherited_field);
+        let texts = field.map(|f| f.0.texts(node)).unwrap_or_default();
+        self.matches_texts(node, field, &texts)
+    }
+
+    /// Evaluates the predicates against already-resolved `texts`; nested conditions without a
+    /// `field` of their own reuse them.
+    fn matches_texts(&self, node: &Value, field: Option<&FieldPath>, texts: &[String]) -> bool {
+        let case_insensitive = self.case_insensitive.unwrap_or(false);
+        let text_equals = |text: &str, expected: &str| if case_insensitive { text.eq_ignore_ascii_case(expected) } else { text == expected };
+        let any_text = |predicate: &dyn Fn(&str) -> bool| texts.iter().any(|t| predicate(t));
+        let in_range = |bound: &FilterBound, accept: fn(std::cmp::Ordering) -> bool| any_text(&|t| bound.compare(t).is_some_and(accept));
+
+        self.equals.as_ref().is_none_or(|expected| any_text(&|t| text_equals(t, expected)))
+            && self.not_equals.as_ref().is_none_or(|expected| !any_text(&|t| text_equals(t, expected)))
+            && self.in_list.as_ref().is_none_or(|list| any_text(&|t| list.iter().any(|expected| text_equals(t, expected))))
+            && self.regex.as_ref().is_none_or(|re| any_text(&|t| re.0.is_match(t)))
+            && self.exists.is_none_or(|exists| texts.is_empty() != exists)
+            && self.missing.is_none_or(|missing| texts.is_empty() == missing)
+            && self.gt.as_ref().is_none_or(|b| in_range(b, |o| o.is_gt()))
+            && self.gte.as_ref().is_none_or(|b| in_range(b, |o| o.is_ge()))
+            && self.lt.as_ref().is_none_or(|b| in_range(b, |o| o.is_lt()))
+            && self.lte.as_ref().is_none_or(|b| in_range(b, |o| o.is_le()))
+            && self.between.as_ref().is_none_or(|(low, high)| any_text(&|t| low.compare(t).is_some_and(|o| o.is_ge()) && high.compare(t).is_some_and(|o| o.is_le())))
+            && self.all_of.as_ref().is_none_or(|conds| conds.iter().all(|c| c.matches_child(node, field, texts)))
+            && self.any_of.as_ref().is_none_or(|conds| conds.iter().any(|c| c.matches_child(node, field, texts)))
+            && self.not.as_ref().is_none_or(|cond| !cond.matches_child(node, field, texts))
+    }
+
+    fn matches_child(&self, node: &Value, parent_field: Option<&FieldPath>, parent_texts: &[String]) -> bool {
+        match &self.field {
+            Some(_) => self.matches(node, None),
+            None => self.matches_texts(node, parent_field, parent_texts),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 struct LookupJoinConfig {
     name: String,
-    lookup_array_path: String,
-    lookup_match_field: String,
-    source_match_field: String,
+    lookup_array_path: JsonPath,
+    lookup_match_field: JsonPath,
+    source_match_field: JsonPath,
     #[serde(default = "default_source_match_is_array")]
     source_match_is_array: bool,
     extract_value: ValueExtractionConfig,
@@ -220,19 +1154,29 @@ fn default_source_match_is_array() -> bool {
 }
 
 impl ValueExtractionConfig {
-     fn get_null_ref(&self) -> Option<&String> {
-         match self {
-             ValueExtractionConfig::Field { use_null, .. } => use_null.as_ref(),
-             ValueExtractionConfig::CombineFields { use_null, .. } => use_null.as_ref(),
-         }
-     }
+    /// The extracted string as one part per value; without `split` that is the string itself.
+    /// A string that splits into nothing yields a single missing part, so `use_null` applies.
+    fn split_parts(&self, extracted: Option<String>) -> Vec<Option<String>> {
+        match (&self.options.split, extracted) {
+            (Some(split), Some(text)) => {
+                let parts = split.split(&text);
+                if parts.is_empty() { vec![None] } else { parts.into_iter().map(Some).collect() }
+            }
+            (_, extracted) => vec![extracted],
+        }
+    }
 
-     fn get_confidence_score(&self) -> Option<f32> {
-         match self {
-             ValueExtractionConfig::Field { confidence_score, .. } => *confidence_score,
-             ValueExtractionConfig::CombineFields { confidence_score, .. } => *confidence_score,
-         }
-     }
+    fn validate(&self) -> Result<()> {
+        if let ExtractionSource::Coalesce { fields } = &self.source {
+            if fields.is_empty() { return Err(anyhow::anyhow!("coalesce extraction needs at least one field")); }
+        }
+        if let Some(normalize) = &self.options.normalize {
+            if !(0.0..=1.0).contains(&normalize.invalid_confidence) {
+                return Err(anyhow::anyhow!("normalize.invalid_confidence must be between 0 and 1, got {}", normalize.invalid_confidence));
+            }
+        }
+        self.options.transforms.iter().try_for_each(|t| t.validate())
+    }
 }
 
 fn generate_deterministic_id(prefix: &str, content: &str) -> String {
@@ -242,50 +1186,540 @@ fn generate_deterministic_id(prefix: &str, content: &str) -> String {
     format!("{}-sha256-{}", prefix, hex::encode(result))
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Filter(PathFilter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PathFilter {
+    path: Vec<PathSegment>,
+    test: Option<(PathFilterOp, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PathFilterOp {
+    Eq,
+    Ne,
+}
+
+/// A profile path, compiled when the profile is loaded: a JSON Pointer (optionally with `*`
+/// segments), a `$`-rooted JSONPath expression or a top-level key.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String")]
+struct JsonPath {
+    source: String,
+    segments: Vec<PathSegment>,
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let segments = compile_path(&source)?;
+        Ok(Self { source, segments })
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl std::fmt::Debug for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+/// A value-extraction field: a relative member name unless written as a full `/` or `$` path;
+/// `.` is the node itself.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+struct FieldPath(JsonPath);
+
+impl TryFrom<String> for FieldPath {
+    type Error = anyhow::Error;
+
+    fn try_from(field: String) -> Result<Self> {
+        let segments = match field.as_str() {
+            "." => Vec::new(),
+            f if f.starts_with('$') || f.starts_with('/') => compile_path(f)?,
+            f => compile_path(&format!("/{}", f))?,
+        };
+        Ok(Self(JsonPath { source: field, segments }))
+    }
+}
+
+/// Compiles `path` into segments. Plain pointers become key segments and a path that is neither
+/// a pointer nor `$`-rooted is a single top-level key.
+fn compile_path(path: &str) -> Result<Vec<PathSegment>> {
+    if let Some(expr) = path.strip_prefix('$') {
+        parse_json_path_segments(expr, path)
+    } else if let Some(pointer) = path.strip_prefix('/') {
+        Ok(pointer.split('/')
+            .map(|token| match token {
+                "*" => PathSegment::Wildcard,
+                _ => PathSegment::Key(token.replace("~1", "/").replace("~0", "~")),
+            })
+            .collect())
+    } else {
+        Ok(vec![PathSegment::Key(path.to_string())])
+    }
+}
+
+fn parse_json_path_segments(expr: &str, full_path: &str) -> Result<Vec<PathSegment>> {
+    let mut segments = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => match chars.peek() {
+                Some('*') => { chars.next(); segments.push(PathSegment::Wildcard); }
+                Some('.') => return Err(anyhow::anyhow!("Recursive descent '..' is not supported in path '{}'", full_path)),
+                _ => {
+                    let mut name = String::new();
+                    while let Some(&ch) = chars.peek() {
+                        if ch == '.' || ch == '[' { break; }
+                        name.push(ch);
+                        chars.next();
+                    }
+                    if name.is_empty() { return Err(anyhow::anyhow!("Empty member name in path '{}'", full_path)); }
+                    segments.push(PathSegment::Key(name));
+                }
+            },
+            '[' => {
+                let mut inner = String::new();
+                let mut quote: Option<char> = None;
+                let mut depth = 0;
+                loop {
+                    match chars.next() {
+                        None => return Err(anyhow::anyhow!("Unclosed '[' in path '{}'", full_path)),
+                        Some('\\') if quote.is_some() => { inner.push('\\'); if let Some(ch) = chars.next() { inner.push(ch); } }
+                        Some(ch) if Some(ch) == quote => { quote = None; inner.push(ch); }
+                        Some(ch @ ('\'' | '"')) if quote.is_none() => { quote = Some(ch); inner.push(ch); }
+                        Some(ch @ ('(' | '[')) if quote.is_none() => { depth += 1; inner.push(ch); }
+                        Some(ch @ (')' | ']')) if quote.is_none() && depth > 0 => { depth -= 1; inner.push(ch); }
+                        Some(']') if quote.is_none() => break,
+                        Some(ch) => inner.push(ch),
+                    }
+                }
+                segments.push(parse_bracket_segment(inner.trim(), full_path)?);
+            }
+            _ => return Err(anyhow::anyhow!("Unexpected '{}' in path '{}' (expected '.' or '[')", c, full_path)),
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_bracket_segment(inner: &str, full_path: &str) -> Result<PathSegment> {
+    if inner == "*" {
+        Ok(PathSegment::Wildcard)
+    } else if let Some(filter_expr) = inner.strip_prefix('?') {
+        let filter_expr = filter_expr.trim();
+        let filter_expr = filter_expr.strip_prefix('(').and_then(|e| e.strip_suffix(')'))
+            .ok_or_else(|| anyhow::anyhow!("Filter '{}' in path '{}' must be written as [?(...)]", inner, full_path))?;
+        parse_path_filter(filter_expr.trim(), full_path).map(PathSegment::Filter)
+    } else if inner.starts_with('\'') || inner.starts_with('"') {
+        parse_quoted_literal(inner).map(PathSegment::Key)
+            .ok_or_else(|| anyhow::anyhow!("Malformed quoted member name [{}] in path '{}'", inner, full_path))
+    } else {
+        inner.parse::<i64>().map(PathSegment::Index)
+            .map_err(|_| anyhow::anyhow!("Unsupported selector [{}] in path '{}'", inner, full_path))
+    }
+}
+
+fn parse_quoted_literal(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let body = text.get(1..)?.strip_suffix(quote)?;
+    let mut literal = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => literal.push(chars.next()?),
+            c if c == quote => return None,
+            c => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+fn parse_path_filter(expr: &str, full_path: &str) -> Result<PathFilter> {
+    let mut quote: Option<char> = None;
+    let mut operator = None;
+    for (idx, c) in expr.char_indices() {
+        match c {
+            '\\' if quote.is_some() => {}
+            '\'' | '"' if quote == Some(c) => quote = None,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            '=' | '!' if quote.is_none() && expr[idx + 1..].starts_with('=') => {
+                operator = Some((idx, if c == '=' { PathFilterOp::Eq } else { PathFilterOp::Ne }));
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let (lhs, test) = match operator {
+        Some((idx, op)) => {
+            let rhs = expr[idx + 2..].trim();
+            let literal = match parse_quoted_literal(rhs) {
+                Some(text) => Value::String(text),
+                None => serde_json::from_str::<Value>(rhs)
+                    .ok()
+                    .filter(|v| !v.is_array() && !v.is_object())
+                    .ok_or_else(|| anyhow::anyhow!("Unsupported literal '{}' in filter of path '{}'", rhs, full_path))?,
+            };
+            (expr[..idx].trim(), Some((op, literal)))
+        }
+        None => (expr, None),
+    };
+    let relative = lhs.strip_prefix('@')
+        .ok_or_else(|| anyhow::anyhow!("Filter expression '{}' in path '{}' must start with '@'", expr, full_path))?;
+    Ok(PathFilter { path: parse_json_path_segments(relative, full_path)?, test })
+}
+
+impl PathFilter {
+    fn matches(&self, candidate: &Value) -> bool {
+        let mut found = Vec::new();
+        collect_path_matches(candidate, &self.path, &mut found);
+        match &self.test {
+            None => found.iter().any(|v| !v.is_null()),
+            Some((PathFilterOp::Eq, literal)) => found.iter().any(|v| json_values_equal(v, literal)),
+            Some((PathFilterOp::Ne, literal)) => !found.iter().any(|v| json_values_equal(v, literal)),
+        }
+    }
+}
+
+fn json_values_equal(value: &Value, literal: &Value) -> bool {
+    match (value, literal) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => value == literal,
+    }
+}
+
+fn collect_path_matches<'a>(node: &'a Value, segments: &[PathSegment], out: &mut Vec<&'a Value>) {
+    visit_path_matches(node, segments, &mut None, &mut |matched, _| out.push(matched));
+}
+
+/// Walks `segments` from `node`, calling `visit` for every match. When `pointer` is set it is
+/// kept at the concrete JSON Pointer of the node being visited.
+fn visit_path_matches<'a>(node: &'a Value, segments: &[PathSegment], pointer: &mut Option<String>, visit: &mut dyn FnMut(&'a Value, Option<&str>)) {
+    let Some((segment, rest)) = segments.split_first() else {
+        visit(node, pointer.as_deref());
+        return;
+    };
+    // Tokens are only formatted when a pointer is being tracked.
+    let mut descend = |token: &dyn ToString, child: &'a Value, pointer: &mut Option<String>| {
+        let len = pointer.as_mut().map(|p| { let len = p.len(); push_pointer_token(p, &token.to_string()); len });
+        visit_path_matches(child, rest, pointer, visit);
+        if let (Some(p), Some(len)) = (pointer.as_mut(), len) { p.truncate(len); }
+    };
+    match segment {
+        PathSegment::Key(key) => {
+            let child = match node {
+                Value::Object(map) => map.get(key),
+                Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => None,
+            };
+            if let Some(child) = child { descend(key, child, pointer); }
+        }
+        PathSegment::Index(index) => {
+            if let Value::Array(items) = node {
+                let resolved = if *index < 0 { items.len().checked_sub(index.unsigned_abs() as usize) } else { Some(*index as usize) };
+                if let Some((i, child)) = resolved.and_then(|i| items.get(i).map(|child| (i, child))) { descend(&i, child, pointer); }
+            }
+        }
+        PathSegment::Wildcard => match node {
+            Value::Array(items) => items.iter().enumerate().for_each(|(i, child)| descend(&i, child, pointer)),
+            Value::Object(map) => map.iter().for_each(|(key, child)| descend(key, child, pointer)),
+            _ => {}
+        },
+        PathSegment::Filter(filter) => match node {
+            Value::Array(items) => items.iter().enumerate().filter(|(_, child)| filter.matches(child)).for_each(|(i, child)| descend(&i, child, pointer)),
+            Value::Object(map) => map.iter().filter(|(_, child)| filter.matches(child)).for_each(|(key, child)| descend(key, child, pointer)),
+            _ => {}
+        },
+    }
+}
+
+fn push_pointer_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+impl JsonPath {
+    /// Wildcard and filter paths can match several nodes; all others match at most one.
+    fn is_multi_match(&self) -> bool {
+        self.segments.iter().any(|s| matches!(s, PathSegment::Wildcard | PathSegment::Filter(_)))
+    }
+
+    /// The first match of the path, if any.
+    fn first<'a>(&self, node: &'a Value) -> Option<&'a Value> {
+        let mut found = None;
+        visit_path_matches(node, &self.segments, &mut None, &mut |matched, _| { found.get_or_insert(matched); });
+        found
+    }
+
+    /// Every match of the path; single-match paths yield at most one.
+    fn values<'a>(&self, node: &'a Value) -> Vec<&'a Value> {
+        let mut matches = Vec::new();
+        collect_path_matches(node, &self.segments, &mut matches);
+        matches
+    }
+
+    /// The items an entity, related value or lookup path yields, or `None` when it matches
+    /// nothing. Wildcard and filter paths yield every match; otherwise `is_array` yields the
+    /// elements of the single match (none if it is not an array) and plain paths the match itself.
+    fn items<'a>(&self, node: &'a Value, is_array: bool) -> Option<Vec<&'a Value>> {
+        if self.is_multi_match() {
+            let matches = self.values(node);
+            return (!matches.is_empty()).then_some(matches);
+        }
+        let matched = self.first(node)?;
+        Some(match (is_array, matched.as_array()) {
+            (false, _) => vec![matched],
+            (true, Some(elements)) => elements.iter().collect(),
+            (true, None) => Vec::new(),
+        })
+    }
+
+    /// The concrete JSON Pointers, relative to `node`, of the nodes `values` returns.
+    fn pointers(&self, node: &Value) -> Vec<String> {
+        let mut pointers = Vec::new();
+        visit_path_matches(node, &self.segments, &mut Some(String::new()), &mut |_, pointer| pointers.extend(pointer.map(String::from)));
+        pointers
+    }
+
+    /// Pointers for the `count` nodes `items` returned for `node`, which sits at `node_pointer`.
+    fn item_pointers(&self, node_pointer: &str, node: &Value, is_array: bool, count: usize) -> Vec<String> {
+        let matched = self.pointers(node);
+        let pointer_at = |i: usize| format!("{}{}", node_pointer, matched.get(i).map_or("", String::as_str));
+        (0..count).map(|i| match (self.is_multi_match(), is_array) {
+            (true, _) => pointer_at(i),
+            (false, true) => format!("{}/{}", pointer_at(0), i),
+            (false, false) => pointer_at(0),
+        }).collect()
+    }
+
+    /// The non-null matches as strings, for comparisons in filters and conditions.
+    fn texts(&self, node: &Value) -> Vec<String> {
+        self.values(node).into_iter()
+            .filter(|v| !v.is_null())
+            .map(|v| v.as_str().map(String::from).unwrap_or_else(|| v.to_string()))
+            .collect()
+    }
+}
+
+/// Strings (trimmed), numbers and booleans as text; other JSON types have no scalar form.
+fn scalar_text(value: &Value) -> Option<String> {
+    value.as_str().map(|s| s.trim().to_string()).or_else(|| if value.is_number() || value.is_boolean() { Some(value.to_string()) } else { None })
+}
+
+/// The first non-empty scalar at `field` relative to `node`.
+fn first_scalar_text(node: &Value, field: &FieldPath) -> Option<String> {
+    field.0.values(node).into_iter()
+        .filter_map(scalar_text)
+        .find(|s| !s.is_empty())
+}
+
+fn json_pointer_segments(path: &str) -> Vec<String> {
+    match path.strip_prefix('/') {
+        Some("") => Vec::new(),
+        Some(pointer) => pointer.split('/').map(|s| s.replace("~1", "/").replace("~0", "~")).collect(),
+        None if path.is_empty() => Vec::new(),
+        None => vec![path.to_string()],
+    }
+}
+
+/// Walks a JSON document down `path` (object keys only) and streams each element of the
+/// array found there to `on_record`, skipping everything else without materializing it.
+struct RecordArraySeed<'p, F> {
+    path: &'p [String],
+    on_record: &'p mut F,
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> DeserializeSeed<'de> for RecordArraySeed<'_, F> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> Visitor<'de> for RecordArraySeed<'_, F> {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a JSON document containing a record array")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+        let Some((head, rest)) = self.path.split_first() else {
+            while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+            return Ok(false);
+        };
+        let mut found = false;
+        let on_record = self.on_record;
+        while let Some(key) = map.next_key::<String>()? {
+            if !found && key == *head {
+                found = map.next_value_seed(RecordArraySeed { path: rest, on_record: &mut *on_record })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<bool, A::Error> {
+        if !self.path.is_empty() {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(false);
+        }
+        while let Some(record) = seq.next_element::<Value>()? {
+            (self.on_record)(record).map_err(A::Error::custom)?;
+        }
+        Ok(true)
+    }
+
+    fn visit_bool<E: DeError>(self, _: bool) -> Result<bool, E> { Ok(false) }
+    fn visit_i64<E: DeError>(self, _: i64) -> Result<bool, E> { Ok(false) }
+    fn visit_u64<E: DeError>(self, _: u64) -> Result<bool, E> { Ok(false) }
+    fn visit_f64<E: DeError>(self, _: f64) -> Result<bool, E> { Ok(false) }
+    fn visit_str<E: DeError>(self, _: &str) -> Result<bool, E> { Ok(false) }
+    fn visit_unit<E: DeError>(self) -> Result<bool, E> { Ok(false) }
+}
+
+/// Streams each element of the array at the JSON Pointer `array_path` to `on_record` with its
+/// 1-based position, returning whether the array was found. A malformed document fails after the
+/// records before the error have been handed over.
+fn stream_record_array<R: Read>(reader: R, array_path: &str, mut on_record: impl FnMut(usize, Value) -> Result<()>) -> serde_json::Result<bool> {
+    let path_segments = json_pointer_segments(array_path);
+    let mut position = 0;
+    let mut on_numbered_record = |record: Value| {
+        position += 1;
+        on_record(position, record)
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let found = RecordArraySeed { path: &path_segments, on_record: &mut on_numbered_record }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(found)
+}
+
+const RELATIONSHIP_ID_PREFIX: &str = "rel";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum RelationshipIdMode {
+    #[default]
+    Uuid,
+    Deterministic,
+}
+
+fn generate_relationship_id(mode: RelationshipIdMode, table_name: &str, natural_key: &[&str]) -> String {
+    match mode {
+        RelationshipIdMode::Uuid => Uuid::new_v4().to_string(),
+        RelationshipIdMode::Deterministic => {
+            let id_hashing_content = format!("{}|{}", table_name, natural_key.join("|"));
+            generate_deterministic_id(RELATIONSHIP_ID_PREFIX, &id_hashing_content)
+        }
+    }
+}
+
 #[derive(Parser, Clone)]
 #[command(name = "Affiliation Extractor - Multi Profile Runner")]
-#[command(about = "Extracts affiliation data from JSONL.gz files based on multiple profiles defined in a run configuration.")]
+#[command(about = "Extracts affiliation data from JSONL files (plain, gzip, zstd or bzip2) based on multiple profiles defined in a run configuration.")]
 #[command(version = "1.0.0")]
 struct Cli {
     #[arg(long, help = "Path to the run configuration YAML file", required = true)]
     run_config: PathBuf,
-    #[arg(short, long, help = "Output directory for CSV files", required = true)]
+    #[arg(short, long, help = "Output directory for CSV/Parquet files", required = true)]
     output: String,
     #[arg(short, long, default_value = "INFO", help = "Logging level (DEBUG, INFO, WARN, ERROR)")]
     log_level: String,
     #[arg(short, long, default_value = "0", help = "Number of threads to use (0 for auto)")]
     threads: usize,
-    #[arg(short, long, default_value = "10000", help = "Size of batches sent to writer thread")]
+    #[arg(short, long, default_value = "10000", help = "Number of rows buffered per file before a batch is sent to the writer thread")]
     batch_size: usize,
     #[arg(long, help = "Flag to enable creation of source/process metadata files")]
     create_metadata_files: bool,
+    #[arg(long, value_enum, default_value_t = RelationshipIdMode::Uuid, help = "How relationship row IDs are generated: random UUIDv4 or a SHA-256 hash of the relationship's natural key")]
+    relationship_ids: RelationshipIdMode,
+    #[arg(long, value_enum, default_value_t = RecordValueDedupPolicy::Exact, help = "How repeated record/value links are deduplicated across files and tasks")]
+    record_value_dedup: RecordValueDedupPolicy,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv, help = "Output format: CSV files, Parquet files, or a DuckDB database")]
+    format: OutputFormat,
+    #[arg(long, default_value = "metadata.db", help = "DuckDB database file name inside the output directory (only used with --format duckdb)")]
+    duckdb_file: PathBuf,
+    #[arg(long, default_value = "1000000", help = "Maximum rows per Parquet row group (only used with --format parquet)")]
+    row_group_size: usize,
+    #[arg(long, help = "Write the input file, line and JSON Pointer behind every record/value and value/value relationship to relationship_provenance")]
+    provenance: bool,
 }
 
-#[derive(Debug, Clone)] struct RecordRow { record_id: String, doi: String }
+#[derive(Debug, Clone)] struct RecordRow { record_id: String, primary_id: String, primary_id_type: String }
+#[derive(Debug, Clone)] struct AlternateIdentifierRow { alternate_identifier_id: String, record_id: String, identifier_type: String, identifier_value: String, process_id: String }
+#[derive(Debug, Clone)] struct RecordAttributeRow { record_attribute_id: String, record_id: String, attribute_name: String, attribute_value: String, process_id: String }
 #[derive(Debug, Clone)] struct ValueRow { value_id: String, value_type: String, value_content: String }
 #[derive(Debug, Clone)] struct ProcessRecordRow { process_record_id: String, process_id: String, record_id: String, relationship_type: String, timestamp: String }
 #[derive(Debug, Clone)] struct ProcessValueRow { process_value_id: String, process_id: String, value_id: String, relationship_type: String, confidence_score: Option<f32>, timestamp: String }
 #[derive(Debug, Clone)] struct RecordValueRow { record_value_id: String, record_id: String, value_id: String, relationship_type: String, ordinal: i32, process_id: String, timestamp: String }
 #[derive(Debug, Clone)] struct ValueValueRow { value_value_id: String, source_value_id: String, target_value_id: String, relationship_type: String, ordinal: Option<i32>, process_id: String, confidence_score: Option<f32>, timestamp: String }
+#[derive(Debug, Clone)] struct RecordRecordRow { record_record_id: String, source_record_id: String, target_record_id: String, relationship_type: String, ordinal: i32, process_id: String, timestamp: String }
+#[derive(Debug, Clone)] struct RelationshipProvenanceRow { relationship_id: String, relationship_table: String, input_file: String, line_number: i64, json_pointer: String }
 
 #[derive(Debug, Default)]
 struct OutputBatch {
     records: Vec<RecordRow>,
+    alternate_identifiers: Vec<AlternateIdentifierRow>,
+    record_attributes: Vec<RecordAttributeRow>,
     values: Vec<ValueRow>,
     process_record_relationships: Vec<ProcessRecordRow>,
     process_value_relationships: Vec<ProcessValueRow>,
     record_value_relationships: Vec<RecordValueRow>,
     value_value_relationships: Vec<ValueValueRow>,
+    record_record_relationships: Vec<RecordRecordRow>,
+    relationship_provenance: Vec<RelationshipProvenanceRow>,
 }
 impl OutputBatch {
-    fn is_empty(&self) -> bool { self.records.is_empty() && self.values.is_empty() && self.process_record_relationships.is_empty() && self.process_value_relationships.is_empty() && self.record_value_relationships.is_empty() && self.value_value_relationships.is_empty() }
-    fn count_rows(&self) -> usize { self.records.len() + self.values.len() + self.process_record_relationships.len() + self.process_value_relationships.len() + self.record_value_relationships.len() + self.value_value_relationships.len() }
+    fn is_empty(&self) -> bool { self.records.is_empty() && self.alternate_identifiers.is_empty() && self.record_attributes.is_empty() && self.values.is_empty() && self.process_record_relationships.is_empty() && self.process_value_relationships.is_empty() && self.record_value_relationships.is_empty() && self.value_value_relationships.is_empty() && self.record_record_relationships.is_empty() && self.relationship_provenance.is_empty() }
+    fn count_rows(&self) -> usize { self.records.len() + self.alternate_identifiers.len() + self.record_attributes.len() + self.values.len() + self.process_record_relationships.len() + self.process_value_relationships.len() + self.record_value_relationships.len() + self.value_value_relationships.len() + self.record_record_relationships.len() + self.relationship_provenance.len() }
 }
 
 type RecordIdMap = Arc<DashMap<String, String>>;
 type ValueIdMap = Arc<DashMap<(String, String), String>>;
 type WrittenValueIdSet = Arc<DashSet<String>>;
-type NullValueIdMap = Arc<HashMap<String, String>>;
+type NullValueIdKey = (String, String);
+type NullValueIdMap = Arc<HashMap<NullValueIdKey, String>>;
+
+#[derive(Debug, Default)]
+struct FileProcessingStats {
+    lines_processed: usize,
+    records_processed: usize,
+    records_missing_id: usize,
+    records_invalid_id: usize,
+    records_filtered_out: usize,
+    json_parsing_errors: usize,
+    batches_sent: usize,
+    rows_sent: usize,
+}
+
+struct RecordContext<'a> {
+    record_id: &'a str,
+    record_json: &'a Value,
+    input_file: &'a Path,
+    line_number: usize,
+}
+
+/// Where a relationship is asserted: the record and, with `--provenance`, the concrete JSON
+/// Pointer of the node being processed.
+#[derive(Clone, Copy)]
+struct AssertionSite<'a> {
+    record: &'a RecordContext<'a>,
+    json_pointer: Option<&'a str>,
+}
 
 struct JsonlProcessor {
     profile: Arc<Profile>,
@@ -293,19 +1727,26 @@ struct JsonlProcessor {
     record_id_map: RecordIdMap,
     value_id_map: ValueIdMap,
     timestamp_str: Arc<String>,
-    active_filters: HashMap<String, String>,
+    active_filters: RecordFilter,
+    batch_size: usize,
+    batch_sender: Sender<OutputBatch>,
+    relationship_id_mode: RelationshipIdMode,
+    provenance: bool,
 }
 
-fn generate_relationship_uuid() -> String { Uuid::new_v4().to_string() }
-
 impl JsonlProcessor {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         profile: Arc<Profile>,
         null_value_ids: NullValueIdMap,
         record_id_map: RecordIdMap,
         value_id_map: ValueIdMap,
         timestamp_str: Arc<String>,
-        active_filters: HashMap<String, String>,
+        active_filters: RecordFilter,
+        batch_size: usize,
+        batch_sender: Sender<OutputBatch>,
+        relationship_id_mode: RelationshipIdMode,
+        provenance: bool,
     ) -> Self {
         Self {
             profile,
@@ -314,181 +1755,376 @@ impl JsonlProcessor {
             value_id_map,
             timestamp_str,
             active_filters,
+            batch_size: batch_size.max(1),
+            batch_sender,
+            relationship_id_mode,
+            provenance,
         }
     }
 
-    fn process(&self, filepath: &Path) -> Result<OutputBatch, (PathBuf, anyhow::Error)> {
-        let file = File::open(filepath).map_err(|e| (filepath.to_path_buf(), anyhow::Error::new(e).context(format!("Failed to open file: {}", filepath.display()))))?;
-        let decoder = GzDecoder::new(file);
-        let reader = BufReader::new(decoder);
+    fn send_batch(&self, batch: &mut OutputBatch, filepath: &Path) -> Result<usize, (PathBuf, anyhow::Error)> {
+        if batch.is_empty() { return Ok(0); }
+        let rows_in_batch = batch.count_rows();
+        if let Err(e) = self.batch_sender.send(std::mem::take(batch)) {
+            error!("Failed to send batch from {} to writer thread: {}. Writer likely panicked.", filepath.display(), e);
+            return Err((filepath.to_path_buf(), anyhow::anyhow!("Writer channel closed unexpectedly")));
+        }
+        Ok(rows_in_batch)
+    }
+
+    fn process(&self, filepath: &Path) -> Result<usize, (PathBuf, anyhow::Error)> {
+        let reader = open_input_file(filepath).map_err(|e| (filepath.to_path_buf(), e))?;
+        self.process_reader(reader, filepath)
+    }
+
+    fn process_reader(&self, reader: Box<dyn BufRead + '_>, filepath: &Path) -> Result<usize, (PathBuf, anyhow::Error)> {
         let mut batch = OutputBatch::default();
-        let mut lines_processed = 0;
-        let mut records_processed = 0;
-        let mut records_missing_id = 0;
-        let mut records_filtered_out = 0;
-        let mut json_parsing_errors = 0;
+        let mut stats = FileProcessingStats::default();
+
+        match &self.profile.record_array_path {
+            Some(array_path) => self.process_record_array(reader, array_path, filepath, &mut batch, &mut stats)?,
+            None => self.process_lines(reader, filepath, &mut batch, &mut stats)?,
+        }
+
+        if !batch.is_empty() {
+            stats.rows_sent += self.send_batch(&mut batch, filepath)?;
+            stats.batches_sent += 1;
+        }
+        debug!("Finished {}: Lines={}, Records={}, Skipped(NoID)={}, Skipped(InvalidID)={}, Filtered={}, JsonErrors={}, Batches={}, Rows={}",
+               filepath.display(), stats.lines_processed, stats.records_processed, stats.records_missing_id, stats.records_invalid_id, stats.records_filtered_out, stats.json_parsing_errors, stats.batches_sent, stats.rows_sent);
+
+        Ok(stats.rows_sent)
+    }
 
+    fn process_lines(
+        &self,
+        reader: Box<dyn BufRead + '_>,
+        filepath: &Path,
+        batch: &mut OutputBatch,
+        stats: &mut FileProcessingStats,
+    ) -> Result<(), (PathBuf, anyhow::Error)> {
         for (line_num, line_result) in reader.lines().enumerate() {
-            lines_processed += 1;
+            stats.lines_processed += 1;
             let line_str = match line_result {
                 Ok(s) => s,
-                Err(e) => { warn!("Error reading line {} from {}: {}", line_num + 1, filepath.display(), e); continue; }
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => { warn!("Error reading line {} from {}: {}", line_num + 1, filepath.display(), e); continue; }
+                Err(e) => return Err((filepath.to_path_buf(), anyhow::anyhow!("Error reading line {} from {}: {}", line_num + 1, filepath.display(), e))),
             };
             if line_str.trim().is_empty() { continue; }
 
             match serde_json::from_str::<Value>(&line_str) {
-                Ok(record_json) => {
-                    records_processed += 1;
+                Ok(record_json) => self.process_record(&record_json, filepath, line_num + 1, batch, stats)?,
+                Err(e) => {
+                    stats.json_parsing_errors += 1;
+                    warn!("Error parsing JSON from {}:{}: {}", filepath.display(), line_num + 1, e);
+                }
+            }
+        }
+        Ok(())
+    }
 
-                    if self.should_filter_out(&record_json).unwrap_or(false) {
-                        records_filtered_out += 1;
-                        continue;
-                    }
+    fn process_record_array(
+        &self,
+        reader: Box<dyn BufRead + '_>,
+        array_path: &str,
+        filepath: &Path,
+        batch: &mut OutputBatch,
+        stats: &mut FileProcessingStats,
+    ) -> Result<(), (PathBuf, anyhow::Error)> {
+        let mut item_num = 0;
+        let mut record_error = None;
+        let found = stream_record_array(reader, array_path, |position, record_json| {
+            item_num = position;
+            stats.lines_processed += 1;
+            self.process_record(&record_json, filepath, item_num, batch, stats).map_err(|(_, e)| {
+                let message = e.to_string();
+                record_error = Some(e);
+                anyhow::anyhow!(message)
+            })
+        });
+        match found {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                warn!("Record array path '{}' not found or not an array in {}", array_path, filepath.display());
+                Ok(())
+            }
+            Err(e) => {
+                let error = record_error.unwrap_or_else(|| {
+                    stats.json_parsing_errors += 1;
+                    anyhow::anyhow!("Error parsing JSON document {} after {} records: {}", filepath.display(), item_num, e)
+                });
+                Err((filepath.to_path_buf(), error))
+            }
+        }
+    }
+
+    fn process_record(
+        &self,
+        record_json: &Value,
+        filepath: &Path,
+        line_num: usize,
+        batch: &mut OutputBatch,
+        stats: &mut FileProcessingStats,
+    ) -> Result<(), (PathBuf, anyhow::Error)> {
+        stats.records_processed += 1;
+
+        if self.should_filter_out(record_json).unwrap_or(false) {
+            stats.records_filtered_out += 1;
+            return Ok(());
+        }
+
+        let record_identifier = &self.profile.record_identifier;
+        let mut identifiers: Vec<(&str, String)> = Vec::new();
+        let mut first_invalid: Option<(IdentifierKind, String, &JsonPath)> = None;
+        for (path, id_type, normalize) in record_identifier.sources() {
+            let Some(raw) = path.first(record_json).and_then(scalar_text).filter(|s| !s.is_empty()) else { continue; };
+            match normalize {
+                Some(kind) => match kind.normalize(&raw) {
+                    Some(normalized) => identifiers.push((id_type, normalized)),
+                    None => { first_invalid.get_or_insert((kind, raw, path)); }
+                },
+                None => identifiers.push((id_type, raw)),
+            }
+        }
+
+        let Some((primary_id_type, primary_id_value)) = identifiers.first().cloned() else {
+            if let Some((kind, raw, path)) = first_invalid {
+                stats.records_invalid_id += 1;
+                debug!("Skipping record in {} line {} with invalid {:?} identifier '{}' at path '{}'", filepath.display(), line_num, kind, raw, path);
+            } else if record_identifier.required {
+                stats.records_missing_id += 1;
+                debug!("Skipping record in {} line {} due to missing required identifier at path '{}'", filepath.display(), line_num, record_identifier.path);
+            } else {
+                stats.records_missing_id += 1;
+                debug!("Skipping record in {} line {} with missing optional identifier at path '{}'", filepath.display(), line_num, record_identifier.path);
+            }
+            return Ok(());
+        };
+
+        let hashing_content = record_id_hashing_content(primary_id_type, &primary_id_value);
+        let record_id = self.record_id_map.entry(hashing_content.clone())
+            .or_insert_with(|| self.generate_record_id(&hashing_content))
+            .value()
+            .clone();
+
+        batch.records.push(RecordRow { record_id: record_id.clone(), primary_id: primary_id_value, primary_id_type: primary_id_type.to_string() });
+        let process_id = &self.profile.process_info.process_id;
+        for (id_type, value) in identifiers.into_iter().skip(1) {
+            batch.alternate_identifiers.push(AlternateIdentifierRow {
+                alternate_identifier_id: generate_relationship_id(self.relationship_id_mode, "alternate_identifiers", &[&record_id, id_type, &value, process_id]),
+                record_id: record_id.clone(),
+                identifier_type: id_type.to_string(),
+                identifier_value: value,
+                process_id: process_id.clone(),
+            });
+        }
+        batch.process_record_relationships.push(ProcessRecordRow {
+            process_record_id: generate_relationship_id(self.relationship_id_mode, "process_record_relationships", &[process_id, &record_id, "ingested"]),
+            process_id: process_id.clone(),
+            record_id: record_id.clone(),
+            relationship_type: "ingested".to_string(),
+            timestamp: self.timestamp_str.to_string(),
+        });
+        self.add_record_attribute_rows(&record_id, record_json, batch);
+        self.add_record_link_rows(&record_id, record_json, batch);
+
+        let record_ctx = RecordContext { record_id: &record_id, record_json, input_file: filepath, line_number: line_num };
+        let record_site = AssertionSite { record: &record_ctx, json_pointer: self.provenance.then_some("") };
+        if let Err(e) = self.process_json_node(
+            record_json,
+            record_site,
+            None,
+            &self.profile.entities,
+            batch,
+        ) {
+            warn!("Error processing entities for record {} in {}: {}", record_id, filepath.display(), e);
+        }
+
+        if batch.count_rows() >= self.batch_size {
+            stats.rows_sent += self.send_batch(batch, filepath)?;
+            stats.batches_sent += 1;
+        }
+        Ok(())
+    }
 
-                    let primary_id_value = match self.get_value_at_path(&record_json, &self.profile.record_identifier.path)
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.trim())
-                            .filter(|s| !s.is_empty())
-                    {
-                        Some(id_val) => id_val.to_string(),
-                        None => {
-                            if self.profile.record_identifier.required {
-                                records_missing_id += 1;
-                                debug!("Skipping record in {} line {} due to missing required identifier at path '{}'", filepath.display(), line_num + 1, self.profile.record_identifier.path);
-                                continue;
-                            } else {
-                                records_missing_id += 1;
-                                debug!("Skipping record in {} line {} with missing optional identifier at path '{}'", filepath.display(), line_num + 1, self.profile.record_identifier.path);
-                                continue;
-                            }
-                        }
-                    };
+    /// Array values give one row per element; elements a transform empties are dropped.
+    fn add_record_attribute_rows(&self, record_id: &str, record_json: &Value, batch: &mut OutputBatch) {
+        let process_id = &self.profile.process_info.process_id;
+        for attribute in &self.profile.record_attributes {
+            let mut seen = HashSet::new();
+            let texts = attribute.path.values(record_json).into_iter()
+                .flat_map(|v| match v.as_array() { Some(items) => items.iter().collect(), None => vec![v] })
+                .filter_map(scalar_text)
+                .filter_map(|text| apply_text_transforms(&attribute.transforms, text))
+                .filter(|text| !text.is_empty() && seen.insert(text.clone()));
+            for text in texts {
+                batch.record_attributes.push(RecordAttributeRow {
+                    record_attribute_id: generate_relationship_id(self.relationship_id_mode, "record_attributes", &[record_id, &attribute.name, &text, process_id]),
+                    record_id: record_id.to_string(),
+                    attribute_name: attribute.name.clone(),
+                    attribute_value: text,
+                    process_id: process_id.clone(),
+                });
+            }
+        }
+    }
 
-                    let record_id = self.record_id_map.entry(primary_id_value.clone())
-                        .or_insert_with(|| self.generate_record_id(&primary_id_value))
-                        .value()
-                        .clone();
-
-                    batch.records.push(RecordRow { record_id: record_id.clone(), doi: primary_id_value.clone() });
-                    batch.process_record_relationships.push(ProcessRecordRow {
-                        process_record_id: generate_relationship_uuid(),
-                        process_id: self.profile.process_info.process_id.clone(),
-                        record_id: record_id.clone(),
-                        relationship_type: "ingested".to_string(),
-                        timestamp: self.timestamp_str.to_string(),
-                    });
-
-                    if let Err(e) = self.process_json_node(
-                        &record_json,
-                        &record_id,
-                        None,
-                        &self.profile.entities,
-                        &mut batch,
-                    ) {
-                        warn!("Error processing entities for record {} in {}: {}", record_id, filepath.display(), e);
+    /// Each target gets the ID it would have as a primary identifier, whatever order records are
+    /// processed in, and its first sighting writes a stub `records` row, which a record ingested
+    /// under the same identifier shares. Stubs have no `ingested` process link.
+    fn add_record_link_rows(&self, record_id: &str, record_json: &Value, batch: &mut OutputBatch) {
+        let process_id = &self.profile.process_info.process_id;
+        for link in &self.profile.record_links {
+            let mut seen = HashSet::new();
+            let mut ordinal = 0;
+            let texts = link.path.values(record_json).into_iter()
+                .flat_map(|v| match v.as_array() { Some(items) => items.iter().collect(), None => vec![v] })
+                .filter_map(scalar_text)
+                .filter(|text| !text.is_empty());
+            for raw in texts {
+                let Some(target_id_value) = (match link.normalize {
+                    Some(kind) => kind.normalize(&raw),
+                    None => Some(raw.clone()),
+                }) else {
+                    debug!("Skipping '{}' link from record {} to invalid {} identifier '{}'", link.relationship_type, record_id, link.id_type, raw);
+                    continue;
+                };
+                let hashing_content = record_id_hashing_content(&link.id_type, &target_id_value);
+                let (target_record_id, is_new_stub) = match self.record_id_map.entry(hashing_content) {
+                    Entry::Occupied(entry) => (entry.get().clone(), false),
+                    Entry::Vacant(entry) => {
+                        let id = self.generate_record_id(entry.key());
+                        (entry.insert(id).value().clone(), true)
                     }
-
-                },
-                Err(e) => {
-                    json_parsing_errors += 1;
-                    warn!("Error parsing JSON from {}:{}: {}", filepath.display(), line_num + 1, e);
+                };
+                if target_record_id == record_id || !seen.insert(target_record_id.clone()) { continue; }
+                ordinal += 1;
+                if is_new_stub {
+                    batch.records.push(RecordRow { record_id: target_record_id.clone(), primary_id: target_id_value, primary_id_type: link.id_type.clone() });
                 }
+                batch.record_record_relationships.push(RecordRecordRow {
+                    record_record_id: generate_relationship_id(self.relationship_id_mode, "record_record_relationships", &[record_id, &target_record_id, &link.relationship_type, process_id]),
+                    source_record_id: record_id.to_string(),
+                    target_record_id,
+                    relationship_type: link.relationship_type.clone(),
+                    ordinal,
+                    process_id: process_id.clone(),
+                    timestamp: self.timestamp_str.to_string(),
+                });
             }
         }
-        debug!("Finished {}: Lines={}, Records={}, Skipped(NoID)={}, Filtered={}, JsonErrors={}",
-               filepath.display(), lines_processed, records_processed, records_missing_id, records_filtered_out, json_parsing_errors);
-
-        Ok(batch)
     }
 
     fn process_json_node(
         &self,
         context_node: &Value,
-        record_id: &str,
+        context_site: AssertionSite,
         parent_value_id: Option<&str>,
         entity_configs: &[EntityConfig],
         batch: &mut OutputBatch,
     ) -> Result<()> {
+        let record = context_site.record;
+        let record_id = record.record_id;
         for config in entity_configs {
-            if let Some(entity_data_node) = self.get_value_at_path(context_node, &config.path) {
-
-                let items_to_process: Vec<Value> = if config.is_array {
-                    entity_data_node.as_array().map(|a| a.iter().cloned().collect()).unwrap_or_default()
-                } else if !entity_data_node.is_null() {
-                     vec![entity_data_node.clone()]
-                } else {
-                     Vec::new()
-                };
-
+            if let Some(mut items_to_process) = config.path.items(context_node, config.is_array) {
+                if !config.is_array {
+                    items_to_process.retain(|item| !item.is_null());
+                }
 
-                for (ordinal, item_node) in items_to_process.into_iter().enumerate() {
-                    let current_ordinal = (ordinal + 1) as i32;
-                    let mut current_entity_value_id: Option<String> = None;
+                let pointers = context_site.json_pointer.map(|pointer| config.path.item_pointers(pointer, context_node, config.is_array, items_to_process.len()));
+                let mut next_ordinal: i32 = 1;
+                for (index, item_node) in items_to_process.into_iter().enumerate() {
+                    let item_site = AssertionSite { record, json_pointer: pointers.as_ref().map(|p| p[index].as_str()) };
+                    let mut entity_value_ids: Vec<String> = Vec::new();
 
                     if let Some(val_config) = &config.value_extraction {
-                        match self.extract_value(&item_node, val_config) {
+                        match self.extract_value(item_node, val_config) {
                              Ok((extracted_content, value_type)) => {
-                                 match self.get_or_create_value_id(&extracted_content, &value_type, val_config.get_null_ref()) {
-                                     Ok((final_content, value_id)) => {
-                                         let creation_confidence = val_config.get_confidence_score();
-                                         self.add_value_rows(&value_id, &value_type, &final_content, creation_confidence, batch)?;
-                                         current_entity_value_id = Some(value_id.clone());
-
-                                         if let Some(parent_id) = parent_value_id {
-                                             if let Some(rel_type) = &config.relationship_to_parent {
-                                                 let relationship_confidence = config.relationship_confidence;
-                                                 self.add_value_value_relationship(parent_id, &value_id, rel_type, Some(current_ordinal), relationship_confidence, batch)?;
-                                             }
-                                         } else {
-                                             if let Some(rel_type) = &config.relationship_to_record {
-                                                  self.add_record_value_relationship(record_id, &value_id, rel_type, current_ordinal, batch)?;
+                                 for part in val_config.split_parts(extracted_content) {
+                                     let current_ordinal = next_ordinal;
+                                     next_ordinal += 1;
+                                     match self.get_or_create_value_id(&part, &value_type, val_config, record) {
+                                         Ok((final_content, shared_value_id, creation_confidence)) => {
+                                             self.add_value_rows(&shared_value_id, &value_type, &final_content, creation_confidence, batch)?;
+                                             self.add_raw_value_rows(&shared_value_id, &part, val_config, item_site, batch)?;
+                                             let value_id = match config.identity_scope.scope_key(record_id, parent_value_id, &config.name, current_ordinal) {
+                                                 Some(scope_key) if !self.is_null_value_id(val_config, &shared_value_id) => {
+                                                     let scoped_value_id = self.scoped_value_id(&value_type, &final_content, record, &scope_key);
+                                                     self.add_value_rows(&scoped_value_id, &value_type, &final_content, creation_confidence, batch)?;
+                                                     self.add_value_value_relationship(&scoped_value_id, &shared_value_id, SAME_STRING_AS_RELATIONSHIP, None, None, item_site, batch)?;
+                                                     scoped_value_id
+                                                 }
+                                                 _ => shared_value_id,
+                                             };
+
+                                             if let Some(parent_id) = parent_value_id {
+                                                 if let Some(rel_type) = &config.relationship_to_parent {
+                                                     let relationship_confidence = config.relationship_confidence;
+                                                     self.add_value_value_relationship(parent_id, &value_id, rel_type, Some(current_ordinal), relationship_confidence, item_site, batch)?;
+                                                 }
+                                             } else {
+                                                 if let Some(rel_type) = &config.relationship_to_record {
+                                                      self.add_record_value_relationship(&value_id, rel_type, current_ordinal, item_site, batch)?;
+                                                 }
                                              }
+                                             entity_value_ids.push(value_id);
+                                         },
+                                         Err(e) => {
+                                             warn!("L1: Failed to get/create value ID for entity '{}' in record {}: {}", config.name, record_id, e);
                                          }
-                                     },
-                                     Err(e) => {
-                                         warn!("L1: Failed to get/create value ID for entity '{}' in record {}: {}", config.name, record_id, e);
                                      }
                                  }
                              }
                              Err(e) => {
+                                 next_ordinal += 1;
                                  warn!("Failed to extract primary value for entity '{}' in record {}: {}", config.name, record_id, e);
                              }
                          }
+                    } else {
+                        next_ordinal += 1;
                     }
 
-                    let id_for_children = current_entity_value_id.as_deref().or(parent_value_id);
+                    // With `split`, children of the item are attached to every part's value.
+                    let ids_for_children: Vec<&str> = if entity_value_ids.is_empty() {
+                        parent_value_id.into_iter().collect()
+                    } else {
+                        entity_value_ids.iter().map(String::as_str).collect()
+                    };
 
-                    if let Some(child_or_related_id) = id_for_children {
+                    if ids_for_children.is_empty() && (config.nested_entities.is_some() || config.related_values.is_some()) {
+                        warn!("Cannot process nested/related entities for '{}' in record {} because no parent value ID was established or inherited.", config.name, record_id);
+                    }
+                    for &child_or_related_id in &ids_for_children {
                          if let Some(nested_configs) = &config.nested_entities {
-                              if let Err(e) = self.process_json_node(&item_node, record_id, Some(child_or_related_id), nested_configs, batch) {
+                              if let Err(e) = self.process_json_node(item_node, item_site, Some(child_or_related_id), nested_configs, batch) {
                                   warn!("Error processing nested entities for {} under parent {}: {}", config.name, child_or_related_id, e);
                               }
                          }
 
                          if let Some(related_configs) = &config.related_values {
-                              if let Err(e) = self.process_related_values(&item_node, child_or_related_id, related_configs, batch) {
+                              if let Err(e) = self.process_related_values(item_node, item_site, child_or_related_id, related_configs, batch) {
                                   warn!("Error processing related values for {} under parent {}: {}", config.name, child_or_related_id, e);
                               }
                          }
-                    } else if config.nested_entities.is_some() || config.related_values.is_some() {
-                         warn!("Cannot process nested/related entities for '{}' in record {} because no parent value ID was established or inherited.", config.name, record_id);
                     }
 
 
                     if let Some(join_configs) = &config.lookup_joins {
-                         if let Some(source_value_id) = &current_entity_value_id {
+                         if entity_value_ids.is_empty() && !join_configs.is_empty() {
+                             debug!("Skipping lookup joins for entity '{}' in record {} because the entity itself did not produce a value/ID.", config.name, record_id);
+                         }
+                         for source_value_id in &entity_value_ids {
                              if let Err(e) = self.process_lookup_joins(
                                  context_node,
-                                 &item_node,
+                                 context_site,
+                                 item_node,
+                                 item_site,
                                  source_value_id,
                                  join_configs,
-                                 record_id,
                                  batch,
                              ) {
                                  warn!("Error processing lookup joins for entity '{}' (value ID {}) in record {}: {}", config.name, source_value_id, record_id, e);
                              }
-                         } else if !join_configs.is_empty() {
-                              debug!("Skipping lookup joins for entity '{}' in record {} because the entity itself did not produce a value/ID.", config.name, record_id);
                          }
                     }
 
@@ -500,54 +2136,51 @@ impl JsonlProcessor {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_lookup_joins(
         &self,
         context_node: &Value,
+        context_site: AssertionSite,
         current_item_node: &Value,
+        current_item_site: AssertionSite,
         source_value_id: &str,
         join_configs: &[LookupJoinConfig],
-        record_id: &str,
         batch: &mut OutputBatch,
     ) -> Result<()> {
+        let record = context_site.record;
+        let record_id = record.record_id;
         for join_config in join_configs {
-            let source_ids_to_match: HashSet<String> = match self.get_value_at_path(current_item_node, &join_config.source_match_field) {
-                Some(source_id_node) => {
-                    if join_config.source_match_is_array {
-                        source_id_node.as_array()
-                            .map(|arr| arr.iter()
-                                .filter_map(|v| v.as_str().map(String::from))
-                                .collect::<HashSet<String>>())
-                            .unwrap_or_default()
-                    } else {
-                        source_id_node.as_str()
-                            .map(|s| vec![s.to_string()].into_iter().collect())
-                            .unwrap_or_default()
-                    }
-                }
-                None => HashSet::new(),
-            };
+            let source_ids_to_match: HashSet<String> = join_config.source_match_field.items(current_item_node, join_config.source_match_is_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect();
 
             if source_ids_to_match.is_empty() {
                 debug!("Lookup Join '{}': Source match field '{}' yielded no IDs in record {}. Skipping join.", join_config.name, join_config.source_match_field, record_id);
                 continue;
             }
 
-            match self.get_value_at_path(context_node, &join_config.lookup_array_path) {
+            let lookup_path = &join_config.lookup_array_path;
+            match lookup_path.first(context_node) {
                 Some(lookup_array_node) => {
-                    if let Some(lookup_array) = lookup_array_node.as_array() {
+                    let lookup_array = if lookup_path.is_multi_match() || lookup_array_node.is_array() { lookup_path.items(context_node, true) } else { None };
+                    if let Some(lookup_array) = lookup_array {
                         let mut match_found_for_config = false;
+                        let pointers = context_site.json_pointer.map(|pointer| lookup_path.item_pointers(pointer, context_node, true, lookup_array.len()));
 
-                        for lookup_item in lookup_array {
-                            if let Some(lookup_id) = self.get_value_at_path(lookup_item, &join_config.lookup_match_field).and_then(|v| v.as_str()) {
-                                if source_ids_to_match.contains(lookup_id) {
+                        for (index, lookup_item) in lookup_array.into_iter().enumerate() {
+                            let lookup_item_site = AssertionSite { record, json_pointer: pointers.as_ref().map(|p| p[index].as_str()) };
+                            if let Some(lookup_id) = join_config.lookup_match_field.first(lookup_item).and_then(|v| v.as_str().map(String::from)) {
+                                if source_ids_to_match.contains(&lookup_id) {
                                     match_found_for_config = true;
 
                                     match self.extract_value(lookup_item, &join_config.extract_value) {
                                          Ok((extracted_content, value_type)) => {
-                                             match self.get_or_create_value_id(&extracted_content, &value_type, join_config.extract_value.get_null_ref()) {
-                                                 Ok((final_content, target_value_id)) => {
-                                                     let creation_confidence = join_config.extract_value.get_confidence_score();
+                                             match self.get_or_create_value_id(&extracted_content, &value_type, &join_config.extract_value, record) {
+                                                 Ok((final_content, target_value_id, creation_confidence)) => {
                                                      self.add_value_rows(&target_value_id, &value_type, &final_content, creation_confidence, batch)?;
+                                                     self.add_raw_value_rows(&target_value_id, &extracted_content, &join_config.extract_value, lookup_item_site, batch)?;
 
                                                      let relationship_confidence = join_config.relationship_confidence;
                                                      self.add_value_value_relationship(
@@ -556,6 +2189,7 @@ impl JsonlProcessor {
                                                          &join_config.relationship_to_current,
                                                          None,
                                                          relationship_confidence,
+                                                         lookup_item_site,
                                                          batch
                                                      )?;
                                                  },
@@ -577,13 +2211,13 @@ impl JsonlProcessor {
                         }
 
                         if !match_found_for_config {
-                            if let Some(null_key) = join_config.extract_value.get_null_ref() {
+                            if let Some(null_key) = join_config.extract_value.options.use_null.as_ref() {
                                 debug!("Lookup Join '{}': No matching item found in lookup path '{}' for source IDs derived from '{}' in record {}. Applying null default '{}'.",
                                     join_config.name, join_config.lookup_array_path, join_config.source_match_field, record_id, null_key);
                                 if let Some(null_config) = self.profile.null_values.get(null_key) {
-                                    if let Some(null_id) = self.null_value_ids.get(null_key) {
+                                    if let Some(null_id) = self.null_value_id(null_key) {
                                          self.add_value_rows(null_id, &null_config.value_type, &null_config.content, None, batch)?;
-                                         self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, batch)?;
+                                         self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, current_item_site, batch)?;
                                     } else { warn!("(Lookup Join - No Match) Precomputed null ID not found for key: {}", null_key); }
                                 } else { warn!("(Lookup Join - No Match) Null value config not found for key: {}", null_key); }
                             }
@@ -591,13 +2225,13 @@ impl JsonlProcessor {
 
                     } else {
                         warn!("Lookup Join '{}': Path '{}' did not resolve to an array in record {}.", join_config.name, join_config.lookup_array_path, record_id);
-                        if let Some(null_key) = join_config.extract_value.get_null_ref() {
+                        if let Some(null_key) = join_config.extract_value.options.use_null.as_ref() {
                             debug!("Lookup Join '{}': Lookup path '{}' did not resolve to an array in record {}. Applying null default '{}'.",
                                 join_config.name, join_config.lookup_array_path, record_id, null_key);
                             if let Some(null_config) = self.profile.null_values.get(null_key) {
-                                if let Some(null_id) = self.null_value_ids.get(null_key) {
+                                if let Some(null_id) = self.null_value_id(null_key) {
                                     self.add_value_rows(null_id, &null_config.value_type, &null_config.content, None, batch)?;
-                                    self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, batch)?;
+                                    self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, current_item_site, batch)?;
                                 } else { warn!("(Lookup Join - Bad Path) Precomputed null ID not found for key: {}", null_key); }
                             } else { warn!("(Lookup Join - Bad Path) Null value config not found for key: {}", null_key); }
                         }
@@ -605,13 +2239,13 @@ impl JsonlProcessor {
                 }
                 None => {
                     debug!("Lookup Join '{}': Lookup path '{}' not found in context node for record {}.", join_config.name, join_config.lookup_array_path, record_id);
-                     if let Some(null_key) = join_config.extract_value.get_null_ref() {
+                     if let Some(null_key) = join_config.extract_value.options.use_null.as_ref() {
                         debug!("Lookup Join '{}': Lookup path '{}' not found in record {}. Applying null default '{}'.",
                             join_config.name, join_config.lookup_array_path, record_id, null_key);
                         if let Some(null_config) = self.profile.null_values.get(null_key) {
-                            if let Some(null_id) = self.null_value_ids.get(null_key) {
+                            if let Some(null_id) = self.null_value_id(null_key) {
                                  self.add_value_rows(null_id, &null_config.value_type, &null_config.content, None, batch)?;
-                                 self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, batch)?;
+                                 self.add_value_value_relationship(source_value_id, null_id, &join_config.relationship_to_current, None, None, current_item_site, batch)?;
                             } else { warn!("(Lookup Join - Missing Path) Precomputed null ID not found for key: {}", null_key); }
                         } else { warn!("(Lookup Join - Missing Path) Null value config not found for key: {}", null_key); }
                      }
@@ -624,24 +2258,24 @@ impl JsonlProcessor {
     fn process_related_values(
         &self,
         current_node: &Value,
+        current_site: AssertionSite,
         parent_value_id: &str,
         related_configs: &[RelatedValueConfig],
         batch: &mut OutputBatch,
     ) -> Result<()> {
+        let record = current_site.record;
         for config in related_configs {
-            if let Some(related_data_node) = self.get_value_at_path(current_node, &config.path) {
-                let items_to_check = if config.is_array {
-                    related_data_node.as_array().map(|a| a.iter().cloned().collect()).unwrap_or_default()
-                } else {
-                    vec![related_data_node.clone()]
-                };
+            if let Some(items_to_check) = config.path.items(current_node, config.is_array) {
 
                 let mut found_match_for_config = false;
+                let take_first_match = config.take_first_match.unwrap_or(false);
+                let pointers = current_site.json_pointer.map(|pointer| config.path.item_pointers(pointer, current_node, config.is_array, items_to_check.len()));
 
-                for item in items_to_check {
+                for (index, item) in items_to_check.into_iter().enumerate() {
+                    let item_site = AssertionSite { record, json_pointer: pointers.as_ref().map(|p| p[index].as_str()) };
                     let mut condition_met = true;
                     if let Some(condition) = &config.filter_condition {
-                        match self.check_filter_condition(&item, condition) {
+                        match self.check_filter_condition(item, condition) {
                             Ok(met) => condition_met = met,
                             Err(e) => {
                                 warn!("Error checking filter condition for related value '{}' (path '{}') under parent {}: {}. Skipping item.", config.name, config.path, parent_value_id, e);
@@ -651,18 +2285,19 @@ impl JsonlProcessor {
                     }
 
                     if condition_met {
-                        match self.extract_value(&item, &config.extract_value) {
+                        match self.extract_value(item, &config.extract_value) {
                             Ok((extracted_content, value_type)) => {
-                                match self.get_or_create_value_id(&extracted_content, &value_type, config.extract_value.get_null_ref()) {
-                                    Ok((final_content, value_id)) => {
-                                        let creation_confidence = config.extract_value.get_confidence_score();
+                                match self.get_or_create_value_id(&extracted_content, &value_type, &config.extract_value, record) {
+                                    Ok((final_content, value_id, creation_confidence)) => {
                                         self.add_value_rows(&value_id, &value_type, &final_content, creation_confidence, batch)?;
+                                        self.add_raw_value_rows(&value_id, &extracted_content, &config.extract_value, item_site, batch)?;
 
                                         let relationship_confidence = config.relationship_confidence;
-                                        self.add_value_value_relationship(parent_value_id, &value_id, &config.relationship_to_parent, None, relationship_confidence, batch)?;
+                                        let ordinal = (config.is_array && !take_first_match).then_some((index + 1) as i32);
+                                        self.add_value_value_relationship(parent_value_id, &value_id, &config.relationship_to_parent, ordinal, relationship_confidence, item_site, batch)?;
                                         found_match_for_config = true;
 
-                                        if config.take_first_match.unwrap_or(false) {
+                                        if take_first_match {
                                              break;
                                         }
                                     },
@@ -679,24 +2314,24 @@ impl JsonlProcessor {
                 }
 
                 if !found_match_for_config && config.filter_condition.is_some() {
-                    if let Some(null_key) = config.extract_value.get_null_ref() {
+                    if let Some(null_key) = config.extract_value.options.use_null.as_ref() {
                         debug!("Path '{}' existed for parent {}, but no item met filter condition for related value '{}'. Applying null default '{}'.", config.path, parent_value_id, config.name, null_key);
                         if let Some(null_config) = self.profile.null_values.get(null_key) {
-                            if let Some(null_id) = self.null_value_ids.get(null_key) {
+                            if let Some(null_id) = self.null_value_id(null_key) {
                                  self.add_value_rows(null_id, &null_config.value_type, &null_config.content, None, batch)?;
-                                 self.add_value_value_relationship(parent_value_id, null_id, &config.relationship_to_parent, None, None, batch)?;
+                                 self.add_value_value_relationship(parent_value_id, null_id, &config.relationship_to_parent, None, None, current_site, batch)?;
                             } else { warn!("(Post-filter) Precomputed null ID not found for key: {}", null_key); }
                         } else { warn!("(Post-filter) Null value config not found for key: {}", null_key); }
                     }
                 }
 
             } else {
-                 if let Some(null_key) = config.extract_value.get_null_ref() {
+                 if let Some(null_key) = config.extract_value.options.use_null.as_ref() {
                      debug!("Path '{}' missing for parent {}, applying null default '{}' for related value '{}'.", config.path, parent_value_id, null_key, config.name);
                      if let Some(null_config) = self.profile.null_values.get(null_key) {
-                         if let Some(null_id) = self.null_value_ids.get(null_key) {
+                         if let Some(null_id) = self.null_value_id(null_key) {
                                self.add_value_rows(null_id, &null_config.value_type, &null_config.content, None, batch)?;
-                               self.add_value_value_relationship(parent_value_id, null_id, &config.relationship_to_parent, None, None, batch)?;
+                               self.add_value_value_relationship(parent_value_id, null_id, &config.relationship_to_parent, None, None, current_site, batch)?;
                          } else {
                              warn!("Could not find precomputed null ID for key '{}' when handling missing path '{}' for parent {}", null_key, config.path, parent_value_id);
                          }
@@ -711,76 +2346,64 @@ impl JsonlProcessor {
 
     fn should_filter_out(&self, record: &Value) -> Result<bool> {
         if self.active_filters.is_empty() { return Ok(false); }
-
-        for (key, required_value) in &self.active_filters {
-            if let Some(profile_filter_config) = self.profile.filters.as_ref().and_then(|filters| filters.iter().find(|f| f.cli_arg == *key)) {
-                let mut current_value: Option<String> = self.get_value_at_path(record, &profile_filter_config.path)
-                    .and_then(|v| v.as_str().map(|s| s.to_string()).or_else(|| Some(v.to_string())));
-
-                if current_value.is_none() {
-                    if let Some(fallback_path) = &profile_filter_config.fallback_from {
-                        if let Some(primary_id) = self.get_value_at_path(record, fallback_path).and_then(|v| v.as_str()) {
-                                if (fallback_path == "/DOI" || fallback_path == "DOI") && key == "doi_prefix" {
-                                    current_value = primary_id.split_once('/').map(|(pfx, _)| pfx.to_string());
-                                }
-                        }
-                    }
-                }
-
-                if current_value.as_ref().map_or(true, |cv| cv != required_value) {
-                    return Ok(true);
-                }
-            } else {
-                warn!("Active filter key '{}' not found in profile filter definitions.", key);
-            }
-        }
-        Ok(false)
-    }
-
-    fn get_value_at_path<'a>(&self, node: &'a Value, path: &str) -> Option<&'a Value> {
-        if path.starts_with('/') {
-            node.pointer(path)
-        } else {
-            node.get(path)
-        }
+        Ok(!self.active_filters.matches(record))
     }
 
     fn extract_value(&self, node: &Value, config: &ValueExtractionConfig) -> Result<(Option<String>, String)> {
-        match config {
-            ValueExtractionConfig::Field { field, target_value_type, .. } => {
-                let val = self.get_value_at_path(node, &format!("/{}", field))
-                    .and_then(|v| v.as_str().map(|s| s.trim().to_string()).or_else(|| if v.is_number() || v.is_boolean() { Some(v.to_string()) } else {None}))
-                    .filter(|s| !s.is_empty());
-                Ok((val, target_value_type.clone()))
-            },
-            ValueExtractionConfig::CombineFields { fields, separator, target_value_type, .. } => {
+        let extracted = match &config.source {
+            ExtractionSource::Field { field } => first_scalar_text(node, field),
+            ExtractionSource::CombineFields { fields, separator } => {
                 let parts: Vec<String> = fields.iter().filter_map(|f|
-                    self.get_value_at_path(node, &format!("/{}", f))
-                        .and_then(|v| v.as_str().map(|s| s.trim().to_string()))
-                        .filter(|s| !s.is_empty())
+                    f.0.values(node).into_iter()
+                        .filter_map(|v| v.as_str().map(|s| s.trim().to_string()))
+                        .find(|s| !s.is_empty())
                 ).collect();
-                let combined = if parts.is_empty() { None } else { Some(parts.join(separator)) };
-                Ok((combined, target_value_type.clone()))
-           },
-        }
+                if parts.is_empty() { None } else { Some(parts.join(separator)) }
+            }
+            ExtractionSource::Item => scalar_text(node).filter(|s| !s.is_empty()),
+            ExtractionSource::Coalesce { fields } => fields.iter().find_map(|f| first_scalar_text(node, f)),
+            ExtractionSource::Template { template } => template.render(node),
+        };
+        Ok((extracted, config.options.target_value_type.clone()))
     }
 
+    /// Returns the stored content, its value id and the confidence to record the value with.
     fn get_or_create_value_id(
         &self,
         extracted_content: &Option<String>,
         value_type: &str,
-        null_ref: Option<&String>,
-    ) -> Result<(String, String)> {
-        if let Some(content) = extracted_content {
-            let value_id = self.value_id_map.entry((value_type.to_string(), content.clone()))
-                .or_insert_with(|| self.generate_value_id(value_type, content))
-                .value()
-                .clone();
-            Ok((content.clone(), value_id))
-        } else if let Some(null_key) = null_ref {
+        config: &ValueExtractionConfig,
+        record: &RecordContext,
+    ) -> Result<(String, String, Option<f32>)> {
+        let transforms = &config.options.transforms;
+        let mut confidence = config.options.confidence_score;
+        let mut content = match extracted_content {
+            Some(content) if transforms.is_empty() => Some(Cow::Borrowed(content.as_str())),
+            Some(content) => apply_text_transforms(transforms, content.clone()).map(Cow::Owned),
+            None => None,
+        };
+        if let (Some(normalize), Some(text)) = (config.options.normalize.as_ref(), content.as_deref()) {
+            match normalize.identifier.normalize(text) {
+                Some(normalized) => content = Some(Cow::Owned(normalized)),
+                None if normalize.on_invalid == InvalidIdentifierPolicy::Flag => {
+                    debug!("Invalid {:?} identifier '{}' in record {} kept with confidence {}", normalize.identifier, text, record.record_id, normalize.invalid_confidence);
+                    confidence = Some(confidence.map_or(normalize.invalid_confidence, |c| c.min(normalize.invalid_confidence)));
+                }
+                None if config.options.use_null.as_ref().is_none() => {
+                    return Err(anyhow::anyhow!("Invalid {:?} identifier '{}' and no null default specified", normalize.identifier, text));
+                }
+                None => {
+                    debug!("Invalid {:?} identifier '{}' in record {}, using null default", normalize.identifier, text, record.record_id);
+                    content = None;
+                }
+            }
+        }
+        if let Some(content) = content {
+            Ok((content.to_string(), self.value_id_for(value_type, &content, record), confidence))
+        } else if let Some(null_key) = config.options.use_null.as_ref() {
             if let Some(null_config) = self.profile.null_values.get(null_key) {
-                if let Some(null_id) = self.null_value_ids.get(null_key) {
-                    Ok((null_config.content.clone(), null_id.clone()))
+                if let Some(null_id) = self.null_value_id(null_key) {
+                    Ok((null_config.content.clone(), null_id.clone(), config.options.confidence_score))
                 } else {
                     Err(anyhow::anyhow!("Precomputed null ID not found for key: {}", null_key))
                 }
@@ -792,13 +2415,65 @@ impl JsonlProcessor {
         }
     }
 
+    fn value_id_for(&self, value_type: &str, content: &str, record: &RecordContext) -> String {
+        let id_hashing_content = self.value_id_hashing_content(value_type, content, Some(record));
+        self.value_id_map.entry((value_type.to_string(), id_hashing_content.clone()))
+            .or_insert_with(|| self.generate_value_id(&id_hashing_content))
+            .value()
+            .clone()
+    }
+
+    /// The value ID for an `identity_scope`d entity: the usual hashing content plus `scope_key`.
+    /// Scoped IDs are unique per record or parent, so they bypass `value_id_map` rather than
+    /// growing it by one entry per occurrence.
+    fn scoped_value_id(&self, value_type: &str, content: &str, record: &RecordContext, scope_key: &str) -> String {
+        let id_hashing_content = format!("{}|{}", self.value_id_hashing_content(value_type, content, Some(record)), scope_key);
+        self.generate_value_id(&id_hashing_content)
+    }
+
+    fn is_null_value_id(&self, config: &ValueExtractionConfig, value_id: &str) -> bool {
+        config.options.use_null.as_ref().and_then(|k| self.null_value_id(k)).is_some_and(|null_id| null_id == value_id)
+    }
+
+    /// Records the pre-transform string for `keep_raw` extractions. Null fallbacks are skipped
+    /// so that raw strings are never linked from the shared null values.
+    fn add_raw_value_rows(
+        &self,
+        value_id: &str,
+        extracted_content: &Option<String>,
+        config: &ValueExtractionConfig,
+        site: AssertionSite,
+        batch: &mut OutputBatch,
+    ) -> Result<()> {
+        let (Some(keep_raw), Some(raw_content)) = (config.options.keep_raw.as_ref(), extracted_content) else { return Ok(()); };
+        if self.is_null_value_id(config, value_id) {
+            return Ok(());
+        }
+        let raw_value_id = self.value_id_for(&keep_raw.value_type, raw_content, site.record);
+        self.add_value_rows(&raw_value_id, &keep_raw.value_type, raw_content, None, batch)?;
+        self.add_value_value_relationship(value_id, &raw_value_id, &keep_raw.relationship, None, None, site, batch)
+    }
+
     fn generate_record_id(&self, primary_id_value: &str) -> String {
         generate_deterministic_id(&self.profile.deterministic_ids.record_prefix, primary_id_value)
     }
 
-    fn generate_value_id(&self, value_type: &str, content: &str) -> String {
-        let id_hashing_content = format!("{}:{}", value_type, content);
-        generate_deterministic_id(&self.profile.deterministic_ids.value_prefix, &id_hashing_content)
+    fn generate_value_id(&self, id_hashing_content: &str) -> String {
+        generate_deterministic_id(&self.profile.deterministic_ids.value_prefix, id_hashing_content)
+    }
+
+    fn value_id_hashing_content(&self, value_type: &str, content: &str, record: Option<&RecordContext>) -> String {
+        self.profile.deterministic_ids.value_format.render(&IdTemplateVars {
+            value_type,
+            value_content: content,
+            source_id: &self.profile.source_info.source_id,
+            process_id: &self.profile.process_info.process_id,
+            record,
+        })
+    }
+
+    fn null_value_id(&self, null_key: &str) -> Option<&String> {
+        self.null_value_ids.get(&(self.profile.process_info.process_id.clone(), null_key.to_string()))
     }
 
     fn add_value_rows(
@@ -814,9 +2489,10 @@ impl JsonlProcessor {
             value_type: value_type.to_string(),
             value_content: value_content.to_string(),
         });
+        let process_id = &self.profile.process_info.process_id;
         batch.process_value_relationships.push(ProcessValueRow {
-            process_value_id: generate_relationship_uuid(),
-            process_id: self.profile.process_info.process_id.clone(),
+            process_value_id: generate_relationship_id(self.relationship_id_mode, "process_value_relationships", &[process_id, value_id, "created"]),
+            process_id: process_id.clone(),
             value_id: value_id.to_string(),
             relationship_type: "created".to_string(),
             confidence_score: confidence,
@@ -825,6 +2501,7 @@ impl JsonlProcessor {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn add_value_value_relationship(
         &self,
         source_id: &str,
@@ -832,15 +2509,20 @@ impl JsonlProcessor {
         rel_type: &str,
         ordinal: Option<i32>,
         confidence: Option<f32>,
+        site: AssertionSite,
         batch: &mut OutputBatch,
     ) -> Result<()> {
+        let process_id = &self.profile.process_info.process_id;
+        let ordinal_str = ordinal.map_or_else(String::new, |o| o.to_string());
+        let value_value_id = generate_relationship_id(self.relationship_id_mode, "value_value_relationships", &[source_id, target_id, rel_type, &ordinal_str, process_id]);
+        self.add_provenance_row(&value_value_id, "value_value_relationships", site, batch);
         batch.value_value_relationships.push(ValueValueRow {
-            value_value_id: generate_relationship_uuid(),
+            value_value_id,
             source_value_id: source_id.to_string(),
             target_value_id: target_id.to_string(),
             relationship_type: rel_type.to_string(),
             ordinal,
-            process_id: self.profile.process_info.process_id.clone(),
+            process_id: process_id.clone(),
             confidence_score: confidence,
             timestamp: self.timestamp_str.to_string(),
         });
@@ -848,35 +2530,38 @@ impl JsonlProcessor {
     }
 
     fn add_record_value_relationship(
-        &self, record_id: &str, value_id: &str, rel_type: &str, ordinal: i32, batch: &mut OutputBatch
+        &self, value_id: &str, rel_type: &str, ordinal: i32, site: AssertionSite, batch: &mut OutputBatch
     ) -> Result<()> {
+        let process_id = &self.profile.process_info.process_id;
+        let record_id = site.record.record_id;
+        let record_value_id = generate_relationship_id(self.relationship_id_mode, "record_value_relationships", &[record_id, value_id, rel_type, &ordinal.to_string(), process_id]);
+        self.add_provenance_row(&record_value_id, "record_value_relationships", site, batch);
         batch.record_value_relationships.push(RecordValueRow {
-            record_value_id: generate_relationship_uuid(),
+            record_value_id,
             record_id: record_id.to_string(),
             value_id: value_id.to_string(),
             relationship_type: rel_type.to_string(),
             ordinal,
-            process_id: self.profile.process_info.process_id.clone(),
+            process_id: process_id.clone(),
             timestamp: self.timestamp_str.to_string(),
         });
         Ok(())
     }
 
+    /// Only sites built with `--provenance` carry a pointer, so this is a no-op otherwise.
+    fn add_provenance_row(&self, relationship_id: &str, relationship_table: &str, site: AssertionSite, batch: &mut OutputBatch) {
+        let Some(json_pointer) = site.json_pointer else { return; };
+        batch.relationship_provenance.push(RelationshipProvenanceRow {
+            relationship_id: relationship_id.to_string(),
+            relationship_table: relationship_table.to_string(),
+            input_file: site.record.input_file.display().to_string(),
+            line_number: site.record.line_number as i64,
+            json_pointer: json_pointer.to_string(),
+        });
+    }
+
     fn check_filter_condition(&self, node: &Value, condition: &FilterConditionConfig) -> Result<bool> {
-        if let Some(field_value) = self.get_value_at_path(node, &format!("/{}", condition.field)) {
-             if let Some(field_str) = field_value.as_str() {
-                 let target_str = &condition.equals;
-                 let case_insensitive = condition.case_insensitive.unwrap_or(false);
-                 if case_insensitive {
-                     return Ok(field_str.eq_ignore_ascii_case(target_str));
-                 } else {
-                     return Ok(field_str == target_str);
-                 }
-             } else if field_value.is_number() || field_value.is_boolean() {
-                 return Ok(field_value.to_string().eq_ignore_ascii_case(&condition.equals));
-             }
-        }
-        Ok(false)
+        Ok(condition.matches(node, None))
     }
 }
 
@@ -888,22 +2573,257 @@ trait OutputWriter: Send {
     fn finalize(&mut self) -> Result<()>;
 }
 
-const DATA_TABLE_NAMES: [&str; 6] = [
-    "records", "values", "process_record_relationships", "process_value_relationships", "record_value_relationships", "value_value_relationships",
+const DATA_TABLE_NAMES: [&str; 9] = [
+    "records", "alternate_identifiers", "record_attributes", "values", "process_record_relationships", "process_value_relationships", "record_value_relationships", "value_value_relationships", "record_record_relationships",
 ];
 const METADATA_TABLE_NAMES: [&str; 3] = ["sources", "processes", "source_process_relationships"];
+const PROVENANCE_TABLE_NAME: &str = "relationship_provenance";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum OutputFormat {
+    #[default]
+    Csv,
+    Parquet,
+    /// Requires building with `--features duckdb`.
+    Duckdb,
+}
+
+impl OutputFormat {
+    fn file_extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Duckdb => "db",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OutputOptions {
+    format: OutputFormat,
+    duckdb_file: PathBuf,
+    create_metadata_files: bool,
+    relationship_id_mode: RelationshipIdMode,
+    record_value_dedup: RecordValueDedupPolicy,
+    row_group_size: usize,
+    provenance: bool,
+}
 
+type AlternateIdentifierKey = (String, String, String, String);
+type ProcessRecordRelKey = (String, String, String);
 type ProcessValueRelKey = (String, String, String);
+type RecordValueRelKey = (String, String, String, Option<i32>, String);
 type ValueValueRelKey = (String, String, String, Option<i32>);
+type RecordRecordRelKey = (String, String, String, String);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum RecordValueDedupPolicy {
+    /// Write every record/value link, including exact repeats.
+    All,
+    /// Skip links whose record, value, relationship type, ordinal and process were already written.
+    #[default]
+    Exact,
+    /// Skip links whose record, value, relationship type and process were already written, keeping the first ordinal.
+    IgnoreOrdinal,
+}
+
+impl RecordValueDedupPolicy {
+    fn key_for(&self, row: &RecordValueRow) -> Option<RecordValueRelKey> {
+        let ordinal = match self {
+            RecordValueDedupPolicy::All => return None,
+            RecordValueDedupPolicy::Exact => Some(row.ordinal),
+            RecordValueDedupPolicy::IgnoreOrdinal => None,
+        };
+        Some((row.record_id.clone(), row.value_id.clone(), row.relationship_type.clone(), ordinal, row.process_id.clone()))
+    }
+}
+
+/// Relationship keys already written. With `--provenance` each key also maps to the ID of the row
+/// written for it, which provenance for later duplicates points at; otherwise only the key is kept.
+enum WrittenRelationships<K> {
+    Keys(DashSet<K>),
+    Ids(DashMap<K, String>),
+}
+
+impl<K: Eq + std::hash::Hash> WrittenRelationships<K> {
+    fn new(provenance: bool) -> Self {
+        if provenance { WrittenRelationships::Ids(DashMap::new()) } else { WrittenRelationships::Keys(DashSet::new()) }
+    }
+
+    /// Claims `key` for the relationship row `id`. A row whose key was already claimed is skipped
+    /// and, when IDs are kept, its ID is mapped to the claimed one in `written_ids`.
+    fn retain(&self, key: K, id: &str, written_ids: &mut HashMap<String, String>) -> bool {
+        match self {
+            WrittenRelationships::Keys(keys) => keys.insert(key),
+            WrittenRelationships::Ids(ids) => match ids.entry(key) {
+                Entry::Occupied(entry) => { written_ids.insert(id.to_string(), entry.get().clone()); false }
+                Entry::Vacant(entry) => { entry.insert(id.to_string()); true }
+            },
+        }
+    }
+}
+
+struct WrittenRowTracker {
+    written_value_ids: WrittenValueIdSet,
+    written_record_ids: Arc<DashSet<String>>,
+    written_alternate_identifiers: Arc<DashSet<AlternateIdentifierKey>>,
+    written_process_record_rels: Arc<DashSet<ProcessRecordRelKey>>,
+    written_process_value_rels: Arc<DashSet<ProcessValueRelKey>>,
+    written_record_value_rels: Arc<WrittenRelationships<RecordValueRelKey>>,
+    written_value_value_rels: Arc<WrittenRelationships<ValueValueRelKey>>,
+    written_record_record_rels: Arc<DashSet<RecordRecordRelKey>>,
+    record_value_dedup: RecordValueDedupPolicy,
+}
+
+impl WrittenRowTracker {
+    fn new(record_value_dedup: RecordValueDedupPolicy, provenance: bool) -> Self {
+        Self {
+            written_value_ids: Arc::new(DashSet::new()),
+            written_record_ids: Arc::new(DashSet::new()),
+            written_alternate_identifiers: Arc::new(DashSet::new()),
+            written_process_record_rels: Arc::new(DashSet::new()),
+            written_process_value_rels: Arc::new(DashSet::new()),
+            written_record_value_rels: Arc::new(WrittenRelationships::new(provenance)),
+            written_value_value_rels: Arc::new(WrittenRelationships::new(provenance)),
+            written_record_record_rels: Arc::new(DashSet::new()),
+            record_value_dedup,
+        }
+    }
+
+    fn retain_unwritten(&self, batch: OutputBatch) -> OutputBatch {
+        let records = batch.records.into_iter()
+            .filter(|row| self.written_record_ids.insert(row.record_id.clone()))
+            .collect();
+        let alternate_identifiers = batch.alternate_identifiers.into_iter()
+            .filter(|row| self.written_alternate_identifiers.insert((row.record_id.clone(), row.identifier_type.clone(), row.identifier_value.clone(), row.process_id.clone())))
+            .collect();
+        let values = batch.values.into_iter()
+            .filter(|row| self.written_value_ids.insert(row.value_id.clone()))
+            .collect();
+        let process_record_relationships: Vec<ProcessRecordRow> = batch.process_record_relationships.into_iter()
+            .filter(|row| self.written_process_record_rels.insert((row.process_id.clone(), row.record_id.clone(), row.relationship_type.clone())))
+            .collect();
+        // Attributes are written along with a record's first `ingested` link from their process, so
+        // a record already ingested in an earlier batch adds none. Repeats within the batch are dropped.
+        let newly_ingested: HashSet<(&str, &str)> = process_record_relationships.iter()
+            .filter(|row| row.relationship_type == "ingested")
+            .map(|row| (row.process_id.as_str(), row.record_id.as_str()))
+            .collect();
+        let mut batch_attributes = HashSet::new();
+        let record_attributes = batch.record_attributes.into_iter()
+            .filter(|row| newly_ingested.contains(&(row.process_id.as_str(), row.record_id.as_str()))
+                && batch_attributes.insert((row.record_id.clone(), row.attribute_name.clone(), row.attribute_value.clone())))
+            .collect();
+        let process_value_relationships = batch.process_value_relationships.into_iter()
+            .filter(|row| self.written_process_value_rels.insert((row.process_id.clone(), row.value_id.clone(), row.relationship_type.clone())))
+            .collect();
+        // Relationship IDs of skipped duplicates, mapped to the ID of the row written for them.
+        let mut written_ids: HashMap<String, String> = HashMap::new();
+        let record_value_relationships = batch.record_value_relationships.into_iter()
+            .filter(|row| match self.record_value_dedup.key_for(row) {
+                Some(key) => self.written_record_value_rels.retain(key, &row.record_value_id, &mut written_ids),
+                None => true,
+            })
+            .collect();
+        let value_value_relationships = batch.value_value_relationships.into_iter()
+            .filter(|row| self.written_value_value_rels.retain((row.source_value_id.clone(), row.target_value_id.clone(), row.relationship_type.clone(), row.ordinal), &row.value_value_id, &mut written_ids))
+            .collect();
+        // Every assertion keeps its provenance, pointing at the relationship row that was written.
+        let relationship_provenance = batch.relationship_provenance.into_iter()
+            .map(|mut row| {
+                if let Some(id) = written_ids.get(&row.relationship_id) { row.relationship_id = id.clone(); }
+                row
+            })
+            .collect();
+        let record_record_relationships = batch.record_record_relationships.into_iter()
+            .filter(|row| self.written_record_record_rels.insert((row.source_record_id.clone(), row.target_record_id.clone(), row.relationship_type.clone(), row.process_id.clone())))
+            .collect();
+        OutputBatch {
+            records,
+            alternate_identifiers,
+            record_attributes,
+            values,
+            process_record_relationships,
+            process_value_relationships,
+            record_value_relationships,
+            value_value_relationships,
+            record_record_relationships,
+            relationship_provenance,
+        }
+    }
+
+    fn take_unwritten_null_values(&self, all_profiles_in_run: &[Arc<Profile>], null_value_ids: &NullValueIdMap) -> Vec<ValueRow> {
+        let mut rows = Vec::new();
+        for ((process_id, null_key), value_id) in null_value_ids.iter() {
+             if self.written_value_ids.insert(value_id.clone()) {
+                  let null_config = all_profiles_in_run.iter()
+                      .filter(|p| p.process_info.process_id == *process_id)
+                      .find_map(|p| p.null_values.get(null_key));
+
+                  if let Some(config) = null_config {
+                      rows.push(ValueRow { value_id: value_id.clone(), value_type: config.value_type.clone(), value_content: config.content.clone() });
+                  } else {
+                      warn!("Could not find configuration details for precomputed null key '{}' during finalization.", null_key);
+                  }
+             }
+        }
+        rows
+    }
+}
+
+#[derive(Debug, Clone)] struct SourceRow { source_id: String, source_name: String, source_description: String }
+#[derive(Debug, Clone)] struct ProcessRow { process_id: String, process_name: String, process_description: String }
+#[derive(Debug, Clone)] struct SourceProcessRow { source_process_id: String, source_id: String, process_id: String, relationship_type: String, start_date: String, end_date: Option<String> }
+
+#[derive(Debug, Default)]
+struct MetadataBatch {
+    sources: Vec<SourceRow>,
+    processes: Vec<ProcessRow>,
+    source_process_relationships: Vec<SourceProcessRow>,
+}
+
+fn build_metadata_batch(all_profiles_in_run: &[Arc<Profile>], relationship_id_mode: RelationshipIdMode) -> MetadataBatch {
+    let mut batch = MetadataBatch::default();
+    let mut seen_source_ids = HashSet::new();
+    let mut seen_process_ids = HashSet::new();
+    let current_date = Utc::now().format("%Y-%m-%d").to_string();
+
+    for profile in all_profiles_in_run {
+        let source_id = &profile.source_info.source_id;
+        let process_id = &profile.process_info.process_id;
+
+        if seen_source_ids.insert(source_id.clone()) {
+            batch.sources.push(SourceRow {
+                source_id: source_id.clone(),
+                source_name: profile.source_info.source_name.clone().unwrap_or_default(),
+                source_description: profile.source_info.source_description.clone().unwrap_or_default(),
+            });
+        }
+        if seen_process_ids.insert(process_id.clone()) {
+            batch.processes.push(ProcessRow {
+                process_id: process_id.clone(),
+                process_name: profile.process_info.process_name.clone().unwrap_or_default(),
+                process_description: profile.process_info.process_description.clone().unwrap_or_default(),
+            });
+        }
+        batch.source_process_relationships.push(SourceProcessRow {
+            source_process_id: generate_relationship_id(relationship_id_mode, "source_process_relationships", &[source_id, process_id, "defined_by"]),
+            source_id: source_id.clone(),
+            process_id: process_id.clone(),
+            relationship_type: "defined_by".to_string(),
+            start_date: current_date.clone(),
+            end_date: None,
+        });
+    }
+    batch
+}
 
 struct MultiTableCsvOutput {
     data_writers: HashMap<String, Writer<File>>,
     output_dir: PathBuf,
     rows_written: Arc<DashMap<String, AtomicUsize>>,
     files_created: usize,
-    written_value_ids: WrittenValueIdSet,
-    written_process_value_rels: Arc<DashSet<ProcessValueRelKey>>,
-    written_value_value_rels: Arc<DashSet<ValueValueRelKey>>,
+    tracker: WrittenRowTracker,
     all_profiles_in_run: Vec<Arc<Profile>>,
     null_value_ids: NullValueIdMap,
     create_metadata_files: bool,
@@ -912,12 +2832,10 @@ struct MultiTableCsvOutput {
 impl MultiTableCsvOutput {
     fn new(
         output_dir: PathBuf,
-        written_value_ids: WrittenValueIdSet,
-        written_process_value_rels: Arc<DashSet<ProcessValueRelKey>>,
-        written_value_value_rels: Arc<DashSet<ValueValueRelKey>>,
+        tracker: WrittenRowTracker,
         all_profiles_in_run: Vec<Arc<Profile>>,
         null_value_ids: NullValueIdMap,
-        create_metadata_files: bool,
+        options: &OutputOptions,
     ) -> Result<Self> {
         fs::create_dir_all(&output_dir)?;
         let mut data_writers = HashMap::new();
@@ -925,15 +2843,20 @@ impl MultiTableCsvOutput {
         let mut files_created = 0;
 
         let data_headers: HashMap<&str, Vec<&str>> = [
-            ("records", vec!["record_id", "doi"]),
+            ("records", vec!["record_id", "primary_id", "primary_id_type"]),
+            ("alternate_identifiers", vec!["alternate_identifier_id", "record_id", "identifier_type", "identifier_value", "process_id"]),
+            ("record_attributes", vec!["record_attribute_id", "record_id", "attribute_name", "attribute_value", "process_id"]),
             ("values", vec!["value_id", "value_type", "value_content"]),
             ("process_record_relationships", vec!["process_record_id", "process_id", "record_id", "relationship_type", "timestamp"]),
             ("process_value_relationships", vec!["process_value_id", "process_id", "value_id", "relationship_type", "confidence_score", "timestamp"]),
             ("record_value_relationships", vec!["record_value_id", "record_id", "value_id", "relationship_type", "ordinal", "process_id", "timestamp"]),
             ("value_value_relationships", vec!["value_value_id", "source_value_id", "target_value_id", "relationship_type", "ordinal", "process_id", "confidence_score", "timestamp"]),
+            ("record_record_relationships", vec!["record_record_id", "source_record_id", "target_record_id", "relationship_type", "ordinal", "process_id", "timestamp"]),
+            (PROVENANCE_TABLE_NAME, vec!["relationship_id", "relationship_table", "input_file", "line_number", "json_pointer"]),
         ].iter().cloned().collect();
 
-        for &table_name in DATA_TABLE_NAMES.iter() {
+        let provenance_table = options.provenance.then_some(PROVENANCE_TABLE_NAME);
+        for &table_name in DATA_TABLE_NAMES.iter().chain(provenance_table.iter()) {
             let file_path = output_dir.join(format!("{}.csv", table_name));
             let file = File::create(&file_path)?;
             files_created += 1;
@@ -948,11 +2871,8 @@ impl MultiTableCsvOutput {
             rows_written.insert(table_name.to_string(), AtomicUsize::new(0));
         }
 
-        if create_metadata_files {
+        if options.create_metadata_files {
             info!("Creating metadata files based on profiles used in the run...");
-            let mut seen_source_ids = HashSet::new();
-            let mut seen_process_ids = HashSet::new();
-
             let metadata_headers: HashMap<&str, Vec<&str>> = [
                   ("sources", vec!["source_id", "source_name", "source_description"]),
                   ("processes", vec!["process_id", "process_name", "process_description"]),
@@ -970,44 +2890,31 @@ impl MultiTableCsvOutput {
                  rows_written.insert(table_name.to_string(), AtomicUsize::new(0));
             }
 
-            let current_date = Utc::now().format("%Y-%m-%d").to_string();
-            for profile in &all_profiles_in_run {
-                 let source_id = &profile.source_info.source_id;
-                 let process_id = &profile.process_info.process_id;
-
-                 if seen_source_ids.insert(source_id.clone()) {
-                     if let Some(writer) = metadata_writers.get_mut("sources") {
-                         let _count = rows_written.entry("sources".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(1, Ordering::Relaxed);
-                         writer.write_record(&[
-                             source_id,
-                             profile.source_info.source_name.as_deref().unwrap_or(""),
-                             profile.source_info.source_description.as_deref().unwrap_or(""),
-                         ])?;
-                     }
-                 }
-                 if seen_process_ids.insert(process_id.clone()) {
-                     if let Some(writer) = metadata_writers.get_mut("processes") {
-                         let _count = rows_written.entry("processes".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(1, Ordering::Relaxed);
-                         writer.write_record(&[
-                             process_id,
-                             profile.process_info.process_name.as_deref().unwrap_or(""),
-                             profile.process_info.process_description.as_deref().unwrap_or(""),
-                         ])?;
-                     }
-                 }
-
-                 if let Some(writer) = metadata_writers.get_mut("source_process_relationships") {
-                     let sp_id = generate_relationship_uuid();
-                     let _count = rows_written.entry("source_process_relationships".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(1, Ordering::Relaxed);
-                     writer.write_record(&[
-                        &sp_id,
-                        source_id,
-                        process_id,
-                        "defined_by",
-                        &current_date,
-                        "",
-                     ])?;
-                 }
+            let metadata = build_metadata_batch(&all_profiles_in_run, options.relationship_id_mode);
+            if let Some(writer) = metadata_writers.get_mut("sources") {
+                for row in &metadata.sources {
+                    writer.write_record([&row.source_id, &row.source_name, &row.source_description])?;
+                }
+                rows_written.entry("sources".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(metadata.sources.len(), Ordering::Relaxed);
+            }
+            if let Some(writer) = metadata_writers.get_mut("processes") {
+                for row in &metadata.processes {
+                    writer.write_record([&row.process_id, &row.process_name, &row.process_description])?;
+                }
+                rows_written.entry("processes".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(metadata.processes.len(), Ordering::Relaxed);
+            }
+            if let Some(writer) = metadata_writers.get_mut("source_process_relationships") {
+                for row in &metadata.source_process_relationships {
+                    writer.write_record([
+                        &row.source_process_id,
+                        &row.source_id,
+                        &row.process_id,
+                        &row.relationship_type,
+                        &row.start_date,
+                        row.end_date.as_deref().unwrap_or(""),
+                    ])?;
+                }
+                rows_written.entry("source_process_relationships".to_string()).or_insert(AtomicUsize::new(0)).value().fetch_add(metadata.source_process_relationships.len(), Ordering::Relaxed);
             }
 
             for (_name, writer) in metadata_writers.iter_mut() {
@@ -1023,12 +2930,10 @@ impl MultiTableCsvOutput {
             output_dir,
             rows_written,
             files_created,
-            written_value_ids,
-            written_process_value_rels,
-            written_value_value_rels,
+            tracker,
             all_profiles_in_run,
             null_value_ids,
-            create_metadata_files,
+            create_metadata_files: options.create_metadata_files,
         })
     }
 
@@ -1051,13 +2956,29 @@ impl MultiTableCsvOutput {
 
 impl OutputWriter for MultiTableCsvOutput {
     fn write_batch(&mut self, batch: OutputBatch) -> Result<()> {
+        let batch = self.tracker.retain_unwritten(batch);
+
         if !batch.records.is_empty() {
             let writer = self.get_writer("records")?;
             let count = batch.records.len();
-            for row in batch.records { writer.write_record(&[row.record_id, row.doi])?; }
+            for row in batch.records { writer.write_record(&[row.record_id, row.primary_id, row.primary_id_type])?; }
             self.increment_row_count("records", count);
         }
 
+        if !batch.alternate_identifiers.is_empty() {
+            let writer = self.get_writer("alternate_identifiers")?;
+            let count = batch.alternate_identifiers.len();
+            for row in batch.alternate_identifiers { writer.write_record(&[row.alternate_identifier_id, row.record_id, row.identifier_type, row.identifier_value, row.process_id])?; }
+            self.increment_row_count("alternate_identifiers", count);
+        }
+
+        if !batch.record_attributes.is_empty() {
+            let writer = self.get_writer("record_attributes")?;
+            let count = batch.record_attributes.len();
+            for row in batch.record_attributes { writer.write_record(&[row.record_attribute_id, row.record_id, row.attribute_name, row.attribute_value, row.process_id])?; }
+            self.increment_row_count("record_attributes", count);
+        }
+
         if !batch.process_record_relationships.is_empty() {
             let writer = self.get_writer("process_record_relationships")?;
             let count = batch.process_record_relationships.len();
@@ -1066,33 +2987,19 @@ impl OutputWriter for MultiTableCsvOutput {
         }
 
         if !batch.process_value_relationships.is_empty() {
-            let mut new_rels_to_write = Vec::new();
+            let writer = self.get_writer("process_value_relationships")?;
+            let count = batch.process_value_relationships.len();
             for row in batch.process_value_relationships {
-                let key: ProcessValueRelKey = (
-                    row.process_id.clone(),
-                    row.value_id.clone(),
-                    row.relationship_type.clone()
-                );
-                if self.written_process_value_rels.insert(key) {
-                    new_rels_to_write.push(row);
-                }
-            }
-
-            if !new_rels_to_write.is_empty() {
-                let writer = self.get_writer("process_value_relationships")?;
-                let count = new_rels_to_write.len();
-                for row in new_rels_to_write {
-                    writer.write_record(&[
-                        row.process_value_id,
-                        row.process_id,
-                        row.value_id,
-                        row.relationship_type,
-                        row.confidence_score.map_or("".to_string(), |c| c.to_string()),
-                        row.timestamp
-                    ])?;
-                }
-                self.increment_row_count("process_value_relationships", count);
+                writer.write_record(&[
+                    row.process_value_id,
+                    row.process_id,
+                    row.value_id,
+                    row.relationship_type,
+                    row.confidence_score.map_or("".to_string(), |c| c.to_string()),
+                    row.timestamp
+                ])?;
             }
+            self.increment_row_count("process_value_relationships", count);
         }
 
         if !batch.record_value_relationships.is_empty() {
@@ -1103,69 +3010,441 @@ impl OutputWriter for MultiTableCsvOutput {
         }
 
         if !batch.value_value_relationships.is_empty() {
-            let mut new_rels_to_write = Vec::new();
+            let writer = self.get_writer("value_value_relationships")?;
+            let count = batch.value_value_relationships.len();
             for row in batch.value_value_relationships {
-                 let key: ValueValueRelKey = (
-                      row.source_value_id.clone(),
-                      row.target_value_id.clone(),
-                      row.relationship_type.clone(),
-                      row.ordinal
-                 );
-                 if self.written_value_value_rels.insert(key) {
-                      new_rels_to_write.push(row);
-                 }
+                writer.write_record(&[
+                    row.value_value_id,
+                    row.source_value_id,
+                    row.target_value_id,
+                    row.relationship_type,
+                    row.ordinal.map_or("".to_string(), |o| o.to_string()),
+                    row.process_id,
+                    row.confidence_score.map_or("".to_string(), |c| c.to_string()),
+                    row.timestamp
+                ])?;
+            }
+            self.increment_row_count("value_value_relationships", count);
+        }
+
+        if !batch.record_record_relationships.is_empty() {
+            let writer = self.get_writer("record_record_relationships")?;
+            let count = batch.record_record_relationships.len();
+            for row in batch.record_record_relationships { writer.write_record(&[row.record_record_id, row.source_record_id, row.target_record_id, row.relationship_type, row.ordinal.to_string(), row.process_id, row.timestamp])?; }
+            self.increment_row_count("record_record_relationships", count);
+        }
+
+        if !batch.relationship_provenance.is_empty() {
+            let writer = self.get_writer(PROVENANCE_TABLE_NAME)?;
+            let count = batch.relationship_provenance.len();
+            for row in batch.relationship_provenance { writer.write_record(&[row.relationship_id, row.relationship_table, row.input_file, row.line_number.to_string(), row.json_pointer])?; }
+            self.increment_row_count(PROVENANCE_TABLE_NAME, count);
+        }
+
+        if !batch.values.is_empty() {
+            let writer = self.get_writer("values")?;
+            for row in &batch.values {
+                writer.write_record([&row.value_id, &row.value_type, &row.value_content])?;
+            }
+            self.increment_row_count("values", batch.values.len());
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        info!("Flushing {} data CSV files in directory {}...", self.data_writers.len(), self.output_dir.display());
+        let mut flush_errors = Vec::new();
+        for (name, writer) in self.data_writers.iter_mut() {
+            if let Err(e) = writer.flush() {
+                flush_errors.push(format!("Failed to flush file {}.csv: {}", name, e));
             }
+        }
+        if !flush_errors.is_empty() {
+            Err(anyhow::anyhow!("Errors occurred during final flush:\n - {}", flush_errors.join("\n - ")))
+        } else {
+            info!("All data CSV writers flushed successfully.");
+            Ok(())
+        }
+    }
+
+    fn report_files_created(&self) -> usize { self.files_created }
+
+    fn report_rows_written(&self) -> HashMap<String, usize> {
+        self.rows_written.iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        info!("Finalizing output: ensuring all defined null value entries exist...");
+        let values_file_path = self.output_dir.join("values.csv");
+        let file = OpenOptions::new().append(true).create(true).open(&values_file_path)?;
+        let mut writer = Writer::from_writer(file);
+
+        let null_rows = self.tracker.take_unwritten_null_values(&self.all_profiles_in_run, &self.null_value_ids);
+        for row in &null_rows {
+            writer.write_record([&row.value_id, &row.value_type, &row.value_content])?;
+        }
+        self.increment_row_count("values", null_rows.len());
+
+        writer.flush()?;
+        info!("Null value entry check complete. Added {} null values.", null_rows.len());
+        Ok(())
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<chrono::DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|dt| dt.with_timezone(&Utc))
+        .with_context(|| format!("Invalid RFC 3339 timestamp '{}'", timestamp))
+}
+
+fn parse_timestamp_micros(timestamp: &str) -> Result<i64> {
+    parse_timestamp(timestamp).map(|dt| dt.timestamp_micros())
+}
+
+fn parse_date_days(date: &str) -> Result<i32> {
+    let parsed = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").with_context(|| format!("Invalid date '{}'", date))?;
+    let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid epoch date");
+    Ok((parsed - epoch).num_days() as i32)
+}
+
+fn parquet_table_schema(table_name: &str) -> Option<SchemaRef> {
+    let utc_timestamp = || DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    let fields = match table_name {
+        "records" => vec![
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("primary_id", DataType::Utf8, false),
+            Field::new("primary_id_type", DataType::Utf8, false),
+        ],
+        "alternate_identifiers" => vec![
+            Field::new("alternate_identifier_id", DataType::Utf8, false),
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("identifier_type", DataType::Utf8, false),
+            Field::new("identifier_value", DataType::Utf8, false),
+            Field::new("process_id", DataType::Utf8, false),
+        ],
+        "record_attributes" => vec![
+            Field::new("record_attribute_id", DataType::Utf8, false),
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("attribute_name", DataType::Utf8, false),
+            Field::new("attribute_value", DataType::Utf8, false),
+            Field::new("process_id", DataType::Utf8, false),
+        ],
+        "values" => vec![
+            Field::new("value_id", DataType::Utf8, false),
+            Field::new("value_type", DataType::Utf8, false),
+            Field::new("value_content", DataType::Utf8, false),
+        ],
+        "process_record_relationships" => vec![
+            Field::new("process_record_id", DataType::Utf8, false),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("timestamp", utc_timestamp(), false),
+        ],
+        "process_value_relationships" => vec![
+            Field::new("process_value_id", DataType::Utf8, false),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("value_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("confidence_score", DataType::Float32, true),
+            Field::new("timestamp", utc_timestamp(), false),
+        ],
+        "record_value_relationships" => vec![
+            Field::new("record_value_id", DataType::Utf8, false),
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("value_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("ordinal", DataType::Int32, false),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("timestamp", utc_timestamp(), false),
+        ],
+        "value_value_relationships" => vec![
+            Field::new("value_value_id", DataType::Utf8, false),
+            Field::new("source_value_id", DataType::Utf8, false),
+            Field::new("target_value_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("ordinal", DataType::Int32, true),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("confidence_score", DataType::Float32, true),
+            Field::new("timestamp", utc_timestamp(), false),
+        ],
+        "record_record_relationships" => vec![
+            Field::new("record_record_id", DataType::Utf8, false),
+            Field::new("source_record_id", DataType::Utf8, false),
+            Field::new("target_record_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("ordinal", DataType::Int32, false),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("timestamp", utc_timestamp(), false),
+        ],
+        PROVENANCE_TABLE_NAME => vec![
+            Field::new("relationship_id", DataType::Utf8, false),
+            Field::new("relationship_table", DataType::Utf8, false),
+            Field::new("input_file", DataType::Utf8, false),
+            Field::new("line_number", DataType::Int64, false),
+            Field::new("json_pointer", DataType::Utf8, false),
+        ],
+        "sources" => vec![
+            Field::new("source_id", DataType::Utf8, false),
+            Field::new("source_name", DataType::Utf8, true),
+            Field::new("source_description", DataType::Utf8, true),
+        ],
+        "processes" => vec![
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("process_name", DataType::Utf8, true),
+            Field::new("process_description", DataType::Utf8, true),
+        ],
+        "source_process_relationships" => vec![
+            Field::new("source_process_id", DataType::Utf8, false),
+            Field::new("source_id", DataType::Utf8, false),
+            Field::new("process_id", DataType::Utf8, false),
+            Field::new("relationship_type", DataType::Utf8, false),
+            Field::new("start_date", DataType::Date32, false),
+            Field::new("end_date", DataType::Date32, true),
+        ],
+        _ => return None,
+    };
+    Some(Arc::new(Schema::new(fields)))
+}
+
+fn utf8_column<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+fn timestamp_column<'a>(values: impl Iterator<Item = &'a str>) -> Result<ArrayRef> {
+    let micros = values.map(parse_timestamp_micros).collect::<Result<Vec<i64>>>()?;
+    Ok(Arc::new(TimestampMicrosecondArray::from(micros).with_timezone("UTC")))
+}
+
+struct ParquetOutput {
+    data_writers: HashMap<String, Option<ArrowWriter<File>>>,
+    schemas: HashMap<String, SchemaRef>,
+    output_dir: PathBuf,
+    rows_written: Arc<DashMap<String, AtomicUsize>>,
+    files_created: usize,
+    tracker: WrittenRowTracker,
+    all_profiles_in_run: Vec<Arc<Profile>>,
+    null_value_ids: NullValueIdMap,
+}
+
+impl ParquetOutput {
+    fn new(
+        output_dir: PathBuf,
+        tracker: WrittenRowTracker,
+        all_profiles_in_run: Vec<Arc<Profile>>,
+        null_value_ids: NullValueIdMap,
+        options: &OutputOptions,
+    ) -> Result<Self> {
+        fs::create_dir_all(&output_dir)?;
+        let writer_properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(options.row_group_size.max(1))
+            .build();
+
+        let mut output = Self {
+            data_writers: HashMap::new(),
+            schemas: HashMap::new(),
+            output_dir,
+            rows_written: Arc::new(DashMap::new()),
+            files_created: 0,
+            tracker,
+            all_profiles_in_run,
+            null_value_ids,
+        };
+
+        let mut table_names: Vec<&str> = DATA_TABLE_NAMES.to_vec();
+        if options.provenance {
+            table_names.push(PROVENANCE_TABLE_NAME);
+        }
+        if options.create_metadata_files {
+            table_names.extend(METADATA_TABLE_NAMES.iter());
+        }
+        for table_name in table_names {
+            let schema = parquet_table_schema(table_name)
+                .ok_or_else(|| anyhow::anyhow!("No Parquet schema defined for table: {}", table_name))?;
+            let file_path = output.output_dir.join(format!("{}.parquet", table_name));
+            let file = File::create(&file_path)
+                .with_context(|| format!("Failed to create Parquet file: {}", file_path.display()))?;
+            let writer = ArrowWriter::try_new(file, Arc::clone(&schema), Some(writer_properties.clone()))?;
+            output.files_created += 1;
+            output.data_writers.insert(table_name.to_string(), Some(writer));
+            output.schemas.insert(table_name.to_string(), schema);
+            output.rows_written.insert(table_name.to_string(), AtomicUsize::new(0));
+        }
 
-            if !new_rels_to_write.is_empty() {
-                let writer = self.get_writer("value_value_relationships")?;
-                let count = new_rels_to_write.len();
-                for row in new_rels_to_write {
-                    writer.write_record(&[
-                        row.value_value_id,
-                        row.source_value_id,
-                        row.target_value_id,
-                        row.relationship_type,
-                        row.ordinal.map_or("".to_string(), |o| o.to_string()),
-                        row.process_id,
-                        row.confidence_score.map_or("".to_string(), |c| c.to_string()),
-                        row.timestamp
-                    ])?;
-                }
-                self.increment_row_count("value_value_relationships", count);
-            }
+        if options.create_metadata_files {
+            info!("Creating metadata files based on profiles used in the run...");
+            let metadata = build_metadata_batch(&output.all_profiles_in_run, options.relationship_id_mode);
+            output.write_metadata(metadata)?;
+            info!("Metadata files created and populated.");
+        } else {
+            info!("Skipping creation of metadata files.");
         }
 
-        if !batch.values.is_empty() {
-            let mut new_values_to_write = Vec::new();
-            for row in batch.values {
-                if self.written_value_ids.insert(row.value_id.clone()) {
-                    new_values_to_write.push(row);
-                }
-            }
+        Ok(output)
+    }
 
-            if !new_values_to_write.is_empty() {
-                let writer = self.get_writer("values")?;
-                for row in &new_values_to_write {
-                    writer.write_record(&[&row.value_id, &row.value_type, &row.value_content])?;
-                }
-                self.increment_row_count("values", new_values_to_write.len());
-            }
+    fn write_columns(&mut self, table_name: &str, num_rows: usize, columns: Vec<ArrayRef>) -> Result<()> {
+        if num_rows == 0 { return Ok(()); }
+        let schema = self.schemas.get(table_name)
+            .ok_or_else(|| anyhow::anyhow!("Schema for table '{}' not found", table_name))?;
+        let record_batch = RecordBatch::try_new(Arc::clone(schema), columns)
+            .with_context(|| format!("Failed to build record batch for table '{}'", table_name))?;
+        let writer = self.data_writers.get_mut(table_name)
+            .and_then(|w| w.as_mut())
+            .ok_or_else(|| anyhow::anyhow!("Writer for table '{}' not found or already closed", table_name))?;
+        writer.write(&record_batch)?;
+        if let Some(counter) = self.rows_written.get(table_name) {
+            counter.fetch_add(num_rows, Ordering::Relaxed);
         }
         Ok(())
     }
 
+    fn write_values(&mut self, rows: &[ValueRow]) -> Result<()> {
+        self.write_columns("values", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.value_type.as_str())),
+            utf8_column(rows.iter().map(|r| r.value_content.as_str())),
+        ])
+    }
+
+    fn write_metadata(&mut self, metadata: MetadataBatch) -> Result<()> {
+        let sources = &metadata.sources;
+        self.write_columns("sources", sources.len(), vec![
+            utf8_column(sources.iter().map(|r| r.source_id.as_str())),
+            Arc::new(StringArray::from_iter(sources.iter().map(|r| Some(r.source_name.as_str()).filter(|s| !s.is_empty())))),
+            Arc::new(StringArray::from_iter(sources.iter().map(|r| Some(r.source_description.as_str()).filter(|s| !s.is_empty())))),
+        ])?;
+        let processes = &metadata.processes;
+        self.write_columns("processes", processes.len(), vec![
+            utf8_column(processes.iter().map(|r| r.process_id.as_str())),
+            Arc::new(StringArray::from_iter(processes.iter().map(|r| Some(r.process_name.as_str()).filter(|s| !s.is_empty())))),
+            Arc::new(StringArray::from_iter(processes.iter().map(|r| Some(r.process_description.as_str()).filter(|s| !s.is_empty())))),
+        ])?;
+        let rels = &metadata.source_process_relationships;
+        let start_dates = rels.iter().map(|r| parse_date_days(&r.start_date)).collect::<Result<Vec<i32>>>()?;
+        let end_dates = rels.iter().map(|r| r.end_date.as_deref().map(parse_date_days).transpose()).collect::<Result<Vec<Option<i32>>>>()?;
+        self.write_columns("source_process_relationships", rels.len(), vec![
+            utf8_column(rels.iter().map(|r| r.source_process_id.as_str())),
+            utf8_column(rels.iter().map(|r| r.source_id.as_str())),
+            utf8_column(rels.iter().map(|r| r.process_id.as_str())),
+            utf8_column(rels.iter().map(|r| r.relationship_type.as_str())),
+            Arc::new(Date32Array::from(start_dates)),
+            Arc::new(Date32Array::from(end_dates)),
+        ])
+    }
+}
+
+impl OutputWriter for ParquetOutput {
+    fn write_batch(&mut self, batch: OutputBatch) -> Result<()> {
+        let batch = self.tracker.retain_unwritten(batch);
+
+        let rows = &batch.records;
+        self.write_columns("records", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.primary_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.primary_id_type.as_str())),
+        ])?;
+
+        let rows = &batch.alternate_identifiers;
+        self.write_columns("alternate_identifiers", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.alternate_identifier_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.identifier_type.as_str())),
+            utf8_column(rows.iter().map(|r| r.identifier_value.as_str())),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+        ])?;
+
+        let rows = &batch.record_attributes;
+        self.write_columns("record_attributes", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.record_attribute_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.attribute_name.as_str())),
+            utf8_column(rows.iter().map(|r| r.attribute_value.as_str())),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+        ])?;
+
+        let rows = &batch.process_record_relationships;
+        self.write_columns("process_record_relationships", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.process_record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_type.as_str())),
+            timestamp_column(rows.iter().map(|r| r.timestamp.as_str()))?,
+        ])?;
+
+        let rows = &batch.process_value_relationships;
+        self.write_columns("process_value_relationships", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.process_value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_type.as_str())),
+            Arc::new(Float32Array::from_iter(rows.iter().map(|r| r.confidence_score))),
+            timestamp_column(rows.iter().map(|r| r.timestamp.as_str()))?,
+        ])?;
+
+        let rows = &batch.record_value_relationships;
+        self.write_columns("record_value_relationships", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.record_value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_type.as_str())),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.ordinal))),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+            timestamp_column(rows.iter().map(|r| r.timestamp.as_str()))?,
+        ])?;
+
+        let rows = &batch.value_value_relationships;
+        self.write_columns("value_value_relationships", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.value_value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.source_value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.target_value_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_type.as_str())),
+            Arc::new(Int32Array::from_iter(rows.iter().map(|r| r.ordinal))),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+            Arc::new(Float32Array::from_iter(rows.iter().map(|r| r.confidence_score))),
+            timestamp_column(rows.iter().map(|r| r.timestamp.as_str()))?,
+        ])?;
+
+        let rows = &batch.record_record_relationships;
+        self.write_columns("record_record_relationships", rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.record_record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.source_record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.target_record_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_type.as_str())),
+            Arc::new(Int32Array::from_iter_values(rows.iter().map(|r| r.ordinal))),
+            utf8_column(rows.iter().map(|r| r.process_id.as_str())),
+            timestamp_column(rows.iter().map(|r| r.timestamp.as_str()))?,
+        ])?;
+
+        let rows = &batch.relationship_provenance;
+        self.write_columns(PROVENANCE_TABLE_NAME, rows.len(), vec![
+            utf8_column(rows.iter().map(|r| r.relationship_id.as_str())),
+            utf8_column(rows.iter().map(|r| r.relationship_table.as_str())),
+            utf8_column(rows.iter().map(|r| r.input_file.as_str())),
+            Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.line_number))),
+            utf8_column(rows.iter().map(|r| r.json_pointer.as_str())),
+        ])?;
+
+        self.write_values(&batch.values)
+    }
+
     fn flush(&mut self) -> Result<()> {
-        info!("Flushing {} data CSV files in directory {}...", self.data_writers.len(), self.output_dir.display());
+        info!("Flushing {} Parquet files in directory {}...", self.data_writers.len(), self.output_dir.display());
         let mut flush_errors = Vec::new();
         for (name, writer) in self.data_writers.iter_mut() {
-            if let Err(e) = writer.flush() {
-                flush_errors.push(format!("Failed to flush file {}.csv: {}", name, e));
+            if let Some(writer) = writer.as_mut() {
+                if let Err(e) = writer.flush() {
+                    flush_errors.push(format!("Failed to flush file {}.parquet: {}", name, e));
+                }
             }
         }
         if !flush_errors.is_empty() {
             Err(anyhow::anyhow!("Errors occurred during final flush:\n - {}", flush_errors.join("\n - ")))
         } else {
-            info!("All data CSV writers flushed successfully.");
+            info!("All Parquet writers flushed successfully.");
             Ok(())
         }
     }
@@ -1180,81 +3459,359 @@ impl OutputWriter for MultiTableCsvOutput {
 
     fn finalize(&mut self) -> Result<()> {
         info!("Finalizing output: ensuring all defined null value entries exist...");
-        let values_file_path = self.output_dir.join("values.csv");
-        let file = OpenOptions::new().append(true).create(true).open(&values_file_path)?;
-        let mut writer = Writer::from_writer(file);
-        let mut nulls_added = 0;
+        let null_rows = self.tracker.take_unwritten_null_values(&self.all_profiles_in_run, &self.null_value_ids);
+        self.write_values(&null_rows)?;
+        info!("Null value entry check complete. Added {} null values.", null_rows.len());
 
-        for (null_key, value_id) in self.null_value_ids.iter() {
-             if self.written_value_ids.insert(value_id.clone()) {
-                  let null_config = self.all_profiles_in_run.iter()
-                      .find_map(|p| p.null_values.get(null_key));
+        let mut close_errors = Vec::new();
+        for (name, writer) in self.data_writers.iter_mut() {
+            if let Some(writer) = writer.take() {
+                if let Err(e) = writer.close() {
+                    close_errors.push(format!("Failed to close file {}.parquet: {}", name, e));
+                }
+            }
+        }
+        if !close_errors.is_empty() {
+            return Err(anyhow::anyhow!("Errors occurred while closing Parquet files:\n - {}", close_errors.join("\n - ")));
+        }
+        info!("All Parquet files closed.");
+        Ok(())
+    }
+}
 
-                  if let Some(config) = null_config {
-                      writer.write_record(&[value_id, &config.value_type, &config.content])?;
-                      self.increment_row_count("values", 1);
-                      nulls_added += 1;
-                  } else {
-                      warn!("Could not find configuration details for precomputed null key '{}' during finalization.", null_key);
-                  }
-             }
+/// The DDL `duckdb-affiliation-ingest/create_destroy_db/ingest.sql` also reads, so both ways of building the database get the same tables.
+#[cfg(feature = "duckdb")]
+const DUCKDB_SCHEMA_SQL: &str = include_str!("../sql/schema.sql");
+
+#[cfg(feature = "duckdb")]
+struct DuckDbOutput {
+    conn: Option<duckdb::Connection>,
+    db_path: PathBuf,
+    rows_written: Arc<DashMap<String, AtomicUsize>>,
+    tracker: WrittenRowTracker,
+    all_profiles_in_run: Vec<Arc<Profile>>,
+    null_value_ids: NullValueIdMap,
+}
+
+#[cfg(feature = "duckdb")]
+impl DuckDbOutput {
+    fn new(
+        output_dir: PathBuf,
+        tracker: WrittenRowTracker,
+        all_profiles_in_run: Vec<Arc<Profile>>,
+        null_value_ids: NullValueIdMap,
+        options: &OutputOptions,
+    ) -> Result<Self> {
+        fs::create_dir_all(&output_dir)?;
+        let db_path = output_dir.join(&options.duckdb_file);
+        if db_path.exists() {
+            return Err(anyhow::anyhow!("DuckDB database {} already exists. Remove it or choose another --duckdb-file.", db_path.display()));
         }
+        let conn = duckdb::Connection::open(&db_path)
+            .with_context(|| format!("Failed to open DuckDB database: {}", db_path.display()))?;
+        conn.execute_batch(DUCKDB_SCHEMA_SQL).context("Failed to create DuckDB schema")?;
+        info!("Created DuckDB schema in {}", db_path.display());
 
-        writer.flush()?;
-        info!("Null value entry check complete. Added {} null values.", nulls_added);
+        let rows_written = Arc::new(DashMap::new());
+        let provenance_table = options.provenance.then_some(PROVENANCE_TABLE_NAME);
+        for &table_name in DATA_TABLE_NAMES.iter().chain(provenance_table.iter()).chain(METADATA_TABLE_NAMES.iter()) {
+            rows_written.insert(table_name.to_string(), AtomicUsize::new(0));
+        }
+
+        let mut output = Self { conn: Some(conn), db_path, rows_written, tracker, all_profiles_in_run, null_value_ids };
+
+        if options.create_metadata_files {
+            info!("Populating metadata tables based on profiles used in the run...");
+            let metadata = build_metadata_batch(&output.all_profiles_in_run, options.relationship_id_mode);
+            output.write_metadata(metadata)?;
+        } else {
+            info!("Skipping population of metadata tables.");
+        }
+        Ok(output)
+    }
+
+    fn connection(&self) -> Result<&duckdb::Connection> {
+        self.conn.as_ref().ok_or_else(|| anyhow::anyhow!("DuckDB connection to {} already closed", self.db_path.display()))
+    }
+
+    fn append_rows<T>(&self, table_name: &str, rows: &[T], mut append: impl FnMut(&mut duckdb::Appender, &T) -> Result<()>) -> Result<()> {
+        if rows.is_empty() { return Ok(()); }
+        let mut appender = self.connection()?.appender(table_name)
+            .with_context(|| format!("Failed to create DuckDB appender for table '{}'", table_name))?;
+        for row in rows {
+            append(&mut appender, row).with_context(|| format!("Failed to append row to table '{}'", table_name))?;
+        }
+        appender.flush()?;
+        if let Some(counter) = self.rows_written.get(table_name) {
+            counter.fetch_add(rows.len(), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn write_values(&self, rows: &[ValueRow]) -> Result<()> {
+        self.append_rows("values", rows, |app, r| {
+            app.append_row(duckdb::params![r.value_id, r.value_type, r.value_content])?;
+            Ok(())
+        })
+    }
+
+    fn write_metadata(&mut self, metadata: MetadataBatch) -> Result<()> {
+        self.append_rows("sources", &metadata.sources, |app, r| {
+            app.append_row(duckdb::params![r.source_id, Some(r.source_name.as_str()).filter(|s| !s.is_empty()), Some(r.source_description.as_str()).filter(|s| !s.is_empty())])?;
+            Ok(())
+        })?;
+        self.append_rows("processes", &metadata.processes, |app, r| {
+            app.append_row(duckdb::params![r.process_id, Some(r.process_name.as_str()).filter(|s| !s.is_empty()), Some(r.process_description.as_str()).filter(|s| !s.is_empty())])?;
+            Ok(())
+        })?;
+        self.append_rows("source_process_relationships", &metadata.source_process_relationships, |app, r| {
+            let start_date = chrono::NaiveDate::parse_from_str(&r.start_date, "%Y-%m-%d")?;
+            let end_date = r.end_date.as_deref().map(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d")).transpose()?;
+            app.append_row(duckdb::params![r.source_process_id, r.source_id, r.process_id, r.relationship_type, start_date, end_date])?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "duckdb")]
+impl OutputWriter for DuckDbOutput {
+    fn write_batch(&mut self, batch: OutputBatch) -> Result<()> {
+        let batch = self.tracker.retain_unwritten(batch);
+
+        self.append_rows("records", &batch.records, |app, r| {
+            app.append_row(duckdb::params![r.record_id, r.primary_id, r.primary_id_type])?;
+            Ok(())
+        })?;
+        self.append_rows("alternate_identifiers", &batch.alternate_identifiers, |app, r| {
+            app.append_row(duckdb::params![r.alternate_identifier_id, r.record_id, r.identifier_type, r.identifier_value, r.process_id])?;
+            Ok(())
+        })?;
+        self.append_rows("record_attributes", &batch.record_attributes, |app, r| {
+            app.append_row(duckdb::params![r.record_attribute_id, r.record_id, r.attribute_name, r.attribute_value, r.process_id])?;
+            Ok(())
+        })?;
+        self.write_values(&batch.values)?;
+        self.append_rows("process_record_relationships", &batch.process_record_relationships, |app, r| {
+            app.append_row(duckdb::params![r.process_record_id, r.process_id, r.record_id, r.relationship_type, parse_timestamp(&r.timestamp)?])?;
+            Ok(())
+        })?;
+        self.append_rows("process_value_relationships", &batch.process_value_relationships, |app, r| {
+            app.append_row(duckdb::params![r.process_value_id, r.process_id, r.value_id, r.relationship_type, r.confidence_score, parse_timestamp(&r.timestamp)?])?;
+            Ok(())
+        })?;
+        self.append_rows("record_value_relationships", &batch.record_value_relationships, |app, r| {
+            app.append_row(duckdb::params![r.record_value_id, r.record_id, r.value_id, r.relationship_type, r.ordinal, r.process_id, parse_timestamp(&r.timestamp)?])?;
+            Ok(())
+        })?;
+        self.append_rows("value_value_relationships", &batch.value_value_relationships, |app, r| {
+            app.append_row(duckdb::params![r.value_value_id, r.source_value_id, r.target_value_id, r.relationship_type, r.ordinal, r.process_id, r.confidence_score, parse_timestamp(&r.timestamp)?])?;
+            Ok(())
+        })?;
+        self.append_rows("record_record_relationships", &batch.record_record_relationships, |app, r| {
+            app.append_row(duckdb::params![r.record_record_id, r.source_record_id, r.target_record_id, r.relationship_type, r.ordinal, r.process_id, parse_timestamp(&r.timestamp)?])?;
+            Ok(())
+        })?;
+        self.append_rows(PROVENANCE_TABLE_NAME, &batch.relationship_provenance, |app, r| {
+            app.append_row(duckdb::params![r.relationship_id, r.relationship_table, r.input_file, r.line_number, r.json_pointer])?;
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(conn) = &self.conn {
+            info!("Checkpointing DuckDB database {}...", self.db_path.display());
+            conn.execute_batch("CHECKPOINT;").context("Failed to checkpoint DuckDB database")?;
+        }
+        Ok(())
+    }
+
+    fn report_files_created(&self) -> usize { 1 }
+
+    fn report_rows_written(&self) -> HashMap<String, usize> {
+        self.rows_written.iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        info!("Finalizing output: ensuring all defined null value entries exist...");
+        let null_rows = self.tracker.take_unwritten_null_values(&self.all_profiles_in_run, &self.null_value_ids);
+        self.write_values(&null_rows)?;
+        info!("Null value entry check complete. Added {} null values.", null_rows.len());
+
+        if let Some(conn) = self.conn.take() {
+            conn.close().map_err(|(_, e)| e).with_context(|| format!("Failed to close DuckDB database {}", self.db_path.display()))?;
+        }
+        info!("DuckDB database {} closed.", self.db_path.display());
         Ok(())
     }
 }
 
-struct CsvWriterManager {
+struct OutputWriterManager {
     writer_impl: Box<dyn OutputWriter>,
 }
 
-impl CsvWriterManager {
+impl OutputWriterManager {
     fn new(
         output_dir: PathBuf,
-        written_value_ids: WrittenValueIdSet,
         all_profiles_in_run: Vec<Arc<Profile>>,
         null_value_ids: NullValueIdMap,
-        create_metadata_files: bool,
+        options: OutputOptions,
     ) -> Result<Self> {
-        let written_process_value_rels = Arc::new(DashSet::new());
-        let written_value_value_rels: Arc<DashSet<ValueValueRelKey>> = Arc::new(DashSet::new());
-
-        let strategy = MultiTableCsvOutput::new(
-            output_dir,
-            written_value_ids,
-            written_process_value_rels,
-            written_value_value_rels,
-            all_profiles_in_run,
-            null_value_ids,
-            create_metadata_files
-        )?;
-        Ok(Self { writer_impl: Box::new(strategy) })
+        let tracker = WrittenRowTracker::new(options.record_value_dedup, options.provenance);
+
+        let writer_impl: Box<dyn OutputWriter> = match options.format {
+            OutputFormat::Csv => Box::new(MultiTableCsvOutput::new(output_dir, tracker, all_profiles_in_run, null_value_ids, &options)?),
+            OutputFormat::Parquet => Box::new(ParquetOutput::new(output_dir, tracker, all_profiles_in_run, null_value_ids, &options)?),
+            #[cfg(feature = "duckdb")]
+            OutputFormat::Duckdb => Box::new(DuckDbOutput::new(output_dir, tracker, all_profiles_in_run, null_value_ids, &options)?),
+            #[cfg(not(feature = "duckdb"))]
+            OutputFormat::Duckdb => return Err(anyhow::anyhow!("Cannot write {}: DuckDB output requires building with `--features duckdb`", output_dir.join(&options.duckdb_file).display())),
+        };
+        Ok(Self { writer_impl })
     }
-    fn write_batch(&mut self, batch: OutputBatch) -> Result<()> { self.writer_impl.write_batch(batch).context("Error writing batch via CsvWriterManager") }
-    fn flush_all(&mut self) -> Result<()> { self.writer_impl.flush().context("Error flushing all files via CsvWriterManager") }
+    fn write_batch(&mut self, batch: OutputBatch) -> Result<()> { self.writer_impl.write_batch(batch).context("Error writing batch via OutputWriterManager") }
+    fn flush_all(&mut self) -> Result<()> { self.writer_impl.flush().context("Error flushing all files via OutputWriterManager") }
     fn report_files_created(&self) -> usize { self.writer_impl.report_files_created() }
     fn report_rows_written(&self) -> HashMap<String, usize> { self.writer_impl.report_rows_written() }
-    fn finalize_output(&mut self) -> Result<()> { self.writer_impl.finalize().context("Error finalizing output via CsvWriterManager") }
+    fn finalize_output(&mut self) -> Result<()> { self.writer_impl.finalize().context("Error finalizing output via OutputWriterManager") }
 }
 
-impl Drop for CsvWriterManager {
+impl Drop for OutputWriterManager {
     fn drop(&mut self) {
-        info!("CsvWriterManager dropping. Attempting final flush...");
+        info!("OutputWriterManager dropping. Attempting final flush...");
         if let Err(e) = self.flush_all() {
-            error!("Error flushing CSV writers during cleanup: {}", e);
+            error!("Error flushing output writers during cleanup: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputCodec {
+    Plain,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl InputCodec {
+    fn from_magic_bytes(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(InputCodec::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(InputCodec::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(InputCodec::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("gz") | Some("gzip") => InputCodec::Gzip,
+            Some("zst") | Some("zstd") => InputCodec::Zstd,
+            Some("bz2") => InputCodec::Bzip2,
+            _ => InputCodec::Plain,
         }
     }
+
+    fn detect(path: &Path, header: &[u8]) -> Self {
+        Self::from_magic_bytes(header).unwrap_or_else(|| Self::from_extension(path))
+    }
+
+    fn wrap<'a, R: Read + 'a>(&self, reader: R) -> Result<Box<dyn BufRead + 'a>> {
+        Ok(match self {
+            InputCodec::Plain => Box::new(BufReader::new(reader)),
+            InputCodec::Gzip => Box::new(BufReader::new(GzDecoder::new(reader))),
+            InputCodec::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::new(reader)?)),
+            InputCodec::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        })
+    }
+}
+
+fn open_input_file(filepath: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(filepath).with_context(|| format!("Failed to open file: {}", filepath.display()))?;
+    open_input_reader(file, filepath)
+}
+
+fn open_input_reader<'a, R: Read + 'a>(input: R, filepath: &Path) -> Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(input);
+    let header = reader.fill_buf().with_context(|| format!("Failed to read file header: {}", filepath.display()))?;
+    let codec = InputCodec::detect(filepath, header);
+    debug!("Reading {} as {:?}", filepath.display(), codec);
+    codec.wrap(reader)
+}
+
+#[derive(Debug, Clone)]
+enum InputUnit {
+    File(PathBuf),
+    Archive { path: PathBuf, file_patterns: Vec<String> },
+}
+
+const TAR_ARCHIVE_EXTENSIONS: [&str; 4] = [".tar", ".tar.gz", ".tar.zst", ".tar.bz2"];
+
+/// Whether `path` is a (optionally compressed) tar archive, judged by its extension or, failing
+/// that, by the `ustar` magic at offset 257 of its decompressed contents.
+fn is_tar_archive(path: &Path) -> Result<bool> {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    if TAR_ARCHIVE_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext)) {
+        return Ok(true);
+    }
+    let mut header = Vec::with_capacity(262);
+    open_input_file(path)?.take(262).read_to_end(&mut header)
+        .with_context(|| format!("Failed to read file header: {}", path.display()))?;
+    Ok(header.get(257..262) == Some(b"ustar".as_slice()))
+}
+
+/// Reads the regular-file members of a (optionally compressed) tar archive whose paths match
+/// `file_patterns` and hands each member's raw bytes to `on_member`, so members can be
+/// decompressed and processed elsewhere while the archive is read on. Members are reported as
+/// `<archive>/<member>` so they read like file paths.
+fn for_each_archive_member<F>(archive_path: &Path, file_patterns: &[String], mut on_member: F) -> Result<usize>
+where
+    F: FnMut(PathBuf, Result<Vec<u8>>),
+{
+    let patterns = file_patterns.iter()
+        .map(|p| Pattern::new(p).with_context(|| format!("Invalid file pattern '{}'", p)))
+        .collect::<Result<Vec<_>>>()?;
+    let match_options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+
+    let mut archive = tar::Archive::new(open_input_file(archive_path)?);
+    let mut members_matched = 0;
+    for entry in archive.entries().map_err(|e| anyhow::anyhow!("Failed to read tar archive {}: {}", archive_path.display(), e))? {
+        let mut entry = entry.map_err(|e| anyhow::anyhow!("Failed to read entry from tar archive {}: {}", archive_path.display(), e))?;
+        if !entry.header().entry_type().is_file() { continue; }
+        let entry_path = entry.path().with_context(|| format!("Invalid entry path in tar archive {}", archive_path.display()))?.into_owned();
+        let member_path = entry_path.strip_prefix(".").unwrap_or(&entry_path).to_path_buf();
+        if !patterns.iter().any(|p| p.matches_path_with(&member_path, match_options)) { continue; }
+
+        members_matched += 1;
+        let display_path = archive_path.join(&member_path);
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        let read = entry.read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .with_context(|| format!("Failed to read {}", display_path.display()));
+        on_member(display_path, read);
+    }
+    if members_matched == 0 {
+        warn!("No members found in archive {} matching the patterns: {:?}", archive_path.display(), file_patterns);
+    }
+    Ok(members_matched)
 }
 
-fn find_jsonl_gz_files<P: AsRef<Path>>(directory: P) -> Result<Vec<PathBuf>> {
-    let pattern = directory.as_ref().join("**/*.jsonl.gz");
-    let pattern_str = pattern.to_string_lossy();
-    info!("Searching for files matching pattern: {}", pattern_str);
-    let paths: Vec<PathBuf> = glob(&pattern_str)?.filter_map(Result::ok).collect();
+fn find_input_files<P: AsRef<Path>>(directory: P, file_patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for file_pattern in file_patterns {
+        let pattern = directory.as_ref().join(file_pattern);
+        let pattern_str = pattern.to_string_lossy();
+        info!("Searching for files matching pattern: {}", pattern_str);
+        paths.extend(glob(&pattern_str)?.filter_map(Result::ok).filter(|p| p.is_file()));
+    }
+    paths.sort();
+    paths.dedup();
     if paths.is_empty() {
-        warn!("No files found matching the pattern: {}", pattern_str);
+        warn!("No files found in {} matching the patterns: {:?}", directory.as_ref().display(), file_patterns);
     }
     Ok(paths)
 }
@@ -1280,7 +3837,7 @@ fn get_current_timestamp_str() -> String {
 
 fn precompute_null_value_ids(
     profiles: &[Arc<Profile>]
-) -> Result<HashMap<String, String>> {
+) -> Result<HashMap<NullValueIdKey, String>> {
     let mut map = HashMap::new();
     let mut seen_configs: HashMap<String, NullValueConfig> = HashMap::new();
 
@@ -1292,12 +3849,19 @@ fn precompute_null_value_ids(
                     return Err(anyhow::anyhow!("Inconsistent null value configuration found for key '{}' (type: {}, content: '{}' vs type: {}, content: '{}') across profiles.",
                         key, config.value_type, config.content, existing_config.value_type, existing_config.content));
                 }
-                continue;
+            } else {
+                seen_configs.insert(key.clone(), config.clone());
             }
-            let id_content = format!("{}:{}", config.value_type, config.content);
+            // Null values are shared sentinels, so record-scoped placeholders render empty here.
+            let id_content = id_config.value_format.render(&IdTemplateVars {
+                value_type: &config.value_type,
+                value_content: &config.content,
+                source_id: &profile.source_info.source_id,
+                process_id: &profile.process_info.process_id,
+                record: None,
+            });
             let value_id = generate_deterministic_id(&id_config.value_prefix, &id_content);
-            map.insert(key.clone(), value_id);
-            seen_configs.insert(key.clone(), config.clone());
+            map.insert((profile.process_info.process_id.clone(), key.clone()), value_id);
         }
     }
     Ok(map)
@@ -1305,22 +3869,56 @@ fn precompute_null_value_ids(
 
 fn resolve_task_filters(
     profile_filters: &Option<Vec<FilterConfig>>,
-    task_filters: &HashMap<String, String>
-) -> HashMap<String, String> {
-    let mut resolved = HashMap::new();
+    task_filters: &serde_yaml::Mapping
+) -> Result<RecordFilter> {
+    let mut resolved = Vec::new();
     for (key, value) in task_filters {
-        if profile_filters.as_ref().map_or(false, |pf| pf.iter().any(|f| f.cli_arg == *key)) {
-             resolved.insert(key.clone(), value.clone());
-        } else {
-             warn!("Task filter specified for key '{}', but no corresponding filter definition found in the profile. Ignoring this task filter.", key);
+        let key = key.as_str().ok_or_else(|| anyhow::anyhow!("Task filter keys must be strings, found {:?}", key))?;
+        match key {
+            "all_of" | "any_of" => {
+                let nested = value.as_sequence()
+                    .ok_or_else(|| anyhow::anyhow!("Task filter '{}' must be a list of filter maps", key))?
+                    .iter()
+                    .map(|item| item.as_mapping()
+                        .ok_or_else(|| anyhow::anyhow!("Each entry of task filter '{}' must be a filter map", key))
+                        .and_then(|mapping| resolve_task_filters(profile_filters, mapping)))
+                    .collect::<Result<Vec<_>>>()?;
+                resolved.push(if key == "all_of" { RecordFilter::All(nested) } else { RecordFilter::Any(nested) });
+            }
+            "not" => {
+                let mapping = value.as_mapping().ok_or_else(|| anyhow::anyhow!("Task filter 'not' must be a filter map"))?;
+                resolved.push(RecordFilter::Not(Box::new(resolve_task_filters(profile_filters, mapping)?)));
+            }
+            _ => match profile_filters.as_ref().and_then(|pf| pf.iter().find(|f| f.cli_arg == key)) {
+                Some(filter) => {
+                    let condition = match value {
+                        serde_yaml::Value::Mapping(_) => serde_yaml::from_value::<FilterConditionConfig>(value.clone())
+                            .with_context(|| format!("Invalid condition for task filter '{}'", key))?,
+                        serde_yaml::Value::Sequence(_) => FilterConditionConfig { in_list: Some(serde_yaml::from_value::<Vec<ScalarText>>(value.clone())
+                            .with_context(|| format!("Task filter '{}' lists must contain only scalar values", key))?
+                            .into_iter().map(String::from).collect()), ..Default::default() },
+                        _ => FilterConditionConfig { equals: Some(serde_yaml::from_value::<ScalarText>(value.clone())
+                            .with_context(|| format!("Invalid value for task filter '{}'", key))?.into()), ..Default::default() },
+                    };
+                    if condition.field.is_some() {
+                        return Err(anyhow::anyhow!("Task filter '{}' takes its path from the profile and cannot set 'field'", key));
+                    }
+                    condition.validate(true).with_context(|| format!("Invalid condition for task filter '{}'", key))?;
+                    resolved.push(RecordFilter::Field { filter: filter.clone(), condition: Box::new(condition) });
+                }
+                None => warn!("Task filter specified for key '{}', but no corresponding filter definition found in the profile. Ignoring this task filter.", key),
+            },
         }
     }
-    resolved
+    Ok(RecordFilter::All(resolved))
 }
 
 fn main() -> Result<()> {
     let start_time = Instant::now();
     let cli = Cli::parse();
+    if cli.record_value_dedup == RecordValueDedupPolicy::All && cli.relationship_ids == RelationshipIdMode::Deterministic {
+        return Err(anyhow::anyhow!("--record-value-dedup all cannot be combined with --relationship-ids deterministic: repeated record/value links would get the same ID. Use exact or ignore-ordinal."));
+    }
 
     let log_level = match cli.log_level.to_uppercase().as_str() {
         "DEBUG" => LevelFilter::Debug,
@@ -1350,14 +3948,13 @@ fn main() -> Result<()> {
         .with_context(|| format!("Failed to open run configuration file: {}", run_config_path.display()))?;
     let run_config: RunConfig = serde_yaml::from_reader(run_config_file)
         .with_context(|| format!("Failed to parse run configuration YAML from {}", run_config_path.display()))?;
-    info!("Run config loaded: {} tasks.", run_config.tasks.len());
+    info!("Run config loaded: {} tasks ({}).", run_config.tasks.len(), run_config.description.as_deref().unwrap_or("No description"));
 
     let record_id_map: RecordIdMap = Arc::new(DashMap::new());
     let value_id_map: ValueIdMap = Arc::new(DashMap::new());
-    let written_value_ids: WrittenValueIdSet = Arc::new(DashSet::new());
 
     let mut loaded_profiles: HashMap<PathBuf, Arc<Profile>> = HashMap::new();
-    let mut files_to_process_with_filters: Vec<(PathBuf, Arc<Profile>, HashMap<String, String>)> = Vec::new();
+    let mut files_to_process_with_filters: Vec<(InputUnit, Arc<Profile>, RecordFilter)> = Vec::new();
     let mut all_profiles_in_run_set: HashSet<PathBuf> = HashSet::new();
     let mut all_profiles_in_run_vec: Vec<Arc<Profile>> = Vec::new();
 
@@ -1374,6 +3971,10 @@ fn main() -> Result<()> {
                          .with_context(|| format!("Task {}: Failed to read profile file: {}", i+1, task.profile.display()))?;
                 let parsed_profile: Profile = serde_json::from_str(&profile_content)
                              .with_context(|| format!("Task {}: Failed to parse profile JSON from {}", i+1, task.profile.display()))?;
+                parsed_profile.validate()
+                             .with_context(|| format!("Task {}: Invalid profile {}", i+1, task.profile.display()))?;
+                let value_format = &parsed_profile.deterministic_ids.value_format;
+                info!("  Value ID format: '{}'{}", value_format.format, if value_format.is_record_scoped() { " (record-scoped)" } else { "" });
                 let arc_profile = Arc::new(parsed_profile);
                 entry.insert(Arc::clone(&arc_profile));
                 arc_profile
@@ -1384,16 +3985,27 @@ fn main() -> Result<()> {
               all_profiles_in_run_vec.push(Arc::clone(&profile));
          }
 
-        let resolved_filters = resolve_task_filters(&profile.filters, &task.filters);
+        let resolved_filters = resolve_task_filters(&profile.filters, &task.filters)
+            .with_context(|| format!("Task {}: Invalid filters", i+1))?;
          if !resolved_filters.is_empty() {
-              info!("  Applying task filters: {:?}", resolved_filters);
+              info!("  Applying task filters: {}", serde_json::to_string(&task.filters).unwrap_or_default());
          }
 
-        match find_jsonl_gz_files(&task.input_dir) {
+        let file_patterns = task.file_patterns.clone().unwrap_or_else(|| default_file_patterns(&profile));
+        if task.input_dir.is_file() {
+            if !is_tar_archive(&task.input_dir).with_context(|| format!("Task {}: Failed to read input {}", i+1, task.input_dir.display()))? {
+                return Err(anyhow::anyhow!("Task {}: input_dir {} is a file but not a tar archive ({}). Point input_dir at the directory containing it and select it with file_patterns.",
+                    i+1, task.input_dir.display(), TAR_ARCHIVE_EXTENSIONS.join(", ")));
+            }
+            info!("  Input is an archive; members matching {:?} will be streamed from it.", file_patterns);
+            files_to_process_with_filters.push((InputUnit::Archive { path: task.input_dir.clone(), file_patterns }, Arc::clone(&profile), resolved_filters.clone()));
+            continue;
+        }
+        match find_input_files(&task.input_dir, &file_patterns) {
             Ok(files) => {
-                 info!("  Found {} *.jsonl.gz files for this task.", files.len());
+                 info!("  Found {} input files for this task.", files.len());
                  for file in files {
-                      files_to_process_with_filters.push((file, Arc::clone(&profile), resolved_filters.clone()));
+                      files_to_process_with_filters.push((InputUnit::File(file), Arc::clone(&profile), resolved_filters.clone()));
                  }
             },
             Err(e) => {
@@ -1404,13 +4016,14 @@ fn main() -> Result<()> {
     }
 
     if files_to_process_with_filters.is_empty() {
-        warn!("No .jsonl.gz files found across all tasks. Exiting.");
+        warn!("No input files found across all tasks. Exiting.");
         return Ok(());
     }
-    info!("Total files to process across all tasks: {}", files_to_process_with_filters.len());
+    let archive_count = files_to_process_with_filters.iter().filter(|(input, _, _)| matches!(input, InputUnit::Archive { .. })).count();
+    info!("Total inputs to process across all tasks: {} files, {} archives", files_to_process_with_filters.len() - archive_count, archive_count);
 
     let null_value_ids = Arc::new(precompute_null_value_ids(&all_profiles_in_run_vec)?);
-    info!("Precomputed {} unique null value IDs.", null_value_ids.len());
+    info!("Precomputed {} unique null value IDs.", null_value_ids.values().collect::<HashSet<_>>().len());
 
     let num_threads = if cli.threads == 0 {
         let cores = num_cpus::get();
@@ -1424,7 +4037,7 @@ fn main() -> Result<()> {
         error!("Failed to build global thread pool: {}. Proceeding with default.", e);
     }
 
-    let progress_bar = ProgressBar::new(files_to_process_with_filters.len() as u64);
+    let progress_bar = ProgressBar::new((files_to_process_with_filters.len() - archive_count) as u64);
     progress_bar.set_style(ProgressStyle::default_bar()
         .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta} @ {per_sec}) {msg}")
         .expect("Failed to create progress bar template")
@@ -1435,26 +4048,37 @@ fn main() -> Result<()> {
     let (batch_sender, batch_receiver): (Sender<OutputBatch>, Receiver<OutputBatch>) = bounded(channel_capacity);
     info!("Using writer channel with capacity: {}", channel_capacity);
 
+    let batch_size = cli.batch_size.max(1);
+    info!("Flushing batches to writer every {} rows.", batch_size);
+
     let output_dir_clone = output_dir.clone();
-    let written_value_ids_clone = Arc::clone(&written_value_ids);
     let all_profiles_clone = all_profiles_in_run_vec.clone();
     let null_ids_clone = Arc::clone(&null_value_ids);
-    let create_meta_files = cli.create_metadata_files;
+    let relationship_id_mode = cli.relationship_ids;
+    let output_options = OutputOptions {
+        format: cli.format,
+        duckdb_file: cli.duckdb_file.clone(),
+        create_metadata_files: cli.create_metadata_files,
+        relationship_id_mode,
+        record_value_dedup: cli.record_value_dedup,
+        row_group_size: cli.row_group_size,
+        provenance: cli.provenance,
+    };
+    info!("Output format: {:?}, relationship ID mode: {:?}, record/value link dedup: {:?}", output_options.format, relationship_id_mode, output_options.record_value_dedup);
 
-    let writer_thread = thread::spawn(move || -> Result<CsvWriterManager> {
+    let writer_thread = thread::spawn(move || -> Result<OutputWriterManager> {
         info!("Writer thread started.");
-        let mut csv_writer_manager = CsvWriterManager::new(
+        let mut output_writer_manager = OutputWriterManager::new(
             output_dir_clone,
-            written_value_ids_clone,
             all_profiles_clone,
             null_ids_clone,
-            create_meta_files,
+            output_options,
         )?;
         let mut total_batches_processed = 0;
         for batch in batch_receiver {
              if !batch.is_empty() {
                  let num_rows = batch.count_rows();
-                 if let Err(e) = csv_writer_manager.write_batch(batch) {
+                 if let Err(e) = output_writer_manager.write_batch(batch) {
                      error!("Writer thread error writing batch: {}", e);
                  } else {
                      total_batches_processed += 1;
@@ -1463,23 +4087,22 @@ fn main() -> Result<()> {
              }
         }
         info!("Writer thread finished receiving. Processed {} batches.", total_batches_processed);
-        if let Err(e) = csv_writer_manager.flush_all() { error!("Writer thread error during final flush: {}", e); }
-        if let Err(e) = csv_writer_manager.finalize_output() { error!("Writer thread error during finalize (adding null rows): {}", e); }
+        if let Err(e) = output_writer_manager.flush_all() { error!("Writer thread error during final flush: {}", e); }
+        if let Err(e) = output_writer_manager.finalize_output() { error!("Writer thread error during finalize (adding null rows): {}", e); }
 
-        Ok(csv_writer_manager)
+        Ok(output_writer_manager)
     });
 
     info!("Starting parallel file processing...");
 
      let processing_results: Vec<Result<(), (PathBuf, anyhow::Error)>> = files_to_process_with_filters.par_iter()
-         .map(|(filepath, profile, task_filters_resolved)| {
+         .flat_map_iter(|(input, profile, task_filters_resolved)| {
               let record_id_map_clone = Arc::clone(&record_id_map);
               let value_id_map_clone = Arc::clone(&value_id_map);
               let null_ids_local_clone = Arc::clone(&null_value_ids);
               let timestamp_clone = Arc::clone(&timestamp_str);
               let sender_clone = batch_sender.clone();
               let pb_clone = progress_bar.clone();
-              let process_start_time = Instant::now();
 
               let processor = JsonlProcessor::new(
                    Arc::clone(profile),
@@ -1488,30 +4111,70 @@ fn main() -> Result<()> {
                    value_id_map_clone,
                    timestamp_clone,
                    task_filters_resolved.clone(),
+                   batch_size,
+                   sender_clone,
+                   relationship_id_mode,
+                   cli.provenance,
               );
 
-              match processor.process(filepath) {
-                   Ok(output_batch) => {
-                        let duration = process_start_time.elapsed();
-                        let file_name_msg = filepath.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| filepath.display().to_string());
-                        let rows_in_batch = output_batch.count_rows();
-                        pb_clone.set_message(format!("OK: {} ({} rows, {})", file_name_msg, rows_in_batch, format_elapsed(duration)));
-
-                        if !output_batch.is_empty() {
-                             if let Err(e) = sender_clone.send(output_batch) {
-                                 error!("Failed to send batch from {} to writer thread: {}. Writer likely panicked.", filepath.display(), e);
-                                  return Err((filepath.to_path_buf(), anyhow::anyhow!("Writer channel closed unexpectedly")));
-                             }
+              let report_result = |filepath: &Path, process_start_time: Instant, result: Result<usize, (PathBuf, anyhow::Error)>| {
+                   match result {
+                        Ok(rows_sent) => {
+                             let duration = process_start_time.elapsed();
+                             let file_name_msg = filepath.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| filepath.display().to_string());
+                             pb_clone.set_message(format!("OK: {} ({} rows, {})", file_name_msg, rows_sent, format_elapsed(duration)));
+                             pb_clone.inc(1);
+                             Ok(())
+                        },
+                        Err((path, e)) => {
+                             let file_name_msg = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.display().to_string());
+                             error!("Error processing file {}: {}", path.display(), e);
+                             pb_clone.set_message(format!("ERR: {}", file_name_msg));
+                             pb_clone.inc(1);
+                             Err((path, e))
+                        }
+                   }
+              };
+
+              match input {
+                   InputUnit::File(filepath) => vec![report_result(filepath, Instant::now(), processor.process(filepath))],
+                   InputUnit::Archive { path: archive_path, file_patterns } => {
+                        // The archive is read on this thread and each member is handed to the pool, so
+                        // members decompress and process in parallel. Once `max_in_flight` members are
+                        // waiting, the reader processes the next one itself instead of buffering more.
+                        let member_results = Mutex::new(Vec::new());
+                        let in_flight = AtomicUsize::new(0);
+                        let max_in_flight = rayon::current_num_threads() * 2;
+                        let process_member = |member_path: PathBuf, bytes: Result<Vec<u8>>| {
+                             let process_start_time = Instant::now();
+                             let result = bytes
+                                  .and_then(|bytes| open_input_reader(std::io::Cursor::new(bytes), &member_path))
+                                  .map_err(|e| (member_path.clone(), e))
+                                  .and_then(|reader| processor.process_reader(reader, &member_path));
+                             let report = report_result(&member_path, process_start_time, result);
+                             member_results.lock().unwrap().push(report);
+                        };
+                        let (process_member, in_flight) = (&process_member, &in_flight);
+                        let archive_result = rayon::scope(|scope| {
+                             for_each_archive_member(archive_path, file_patterns, |member_path, bytes| {
+                                  pb_clone.inc_length(1);
+                                  if in_flight.fetch_add(1, Ordering::SeqCst) < max_in_flight {
+                                       scope.spawn(move |_| {
+                                            process_member(member_path, bytes);
+                                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                                       });
+                                  } else {
+                                       in_flight.fetch_sub(1, Ordering::SeqCst);
+                                       process_member(member_path, bytes);
+                                  }
+                             })
+                        });
+                        let mut member_results = member_results.into_inner().unwrap();
+                        if let Err(e) = archive_result {
+                             pb_clone.inc_length(1);
+                             member_results.push(report_result(archive_path, Instant::now(), Err((archive_path.clone(), e))));
                         }
-                        pb_clone.inc(1);
-                        Ok(())
-                   },
-                   Err((path, e)) => {
-                        let file_name_msg = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.display().to_string());
-                        error!("Error processing file {}: {}", path.display(), e);
-                        pb_clone.set_message(format!("ERR: {}", file_name_msg));
-                        pb_clone.inc(1);
-                        Err((path, e))
+                        member_results
                    }
               }
          }).collect();
@@ -1537,7 +4200,7 @@ fn main() -> Result<()> {
 
     let final_row_counts = match writer_manager_result {
          Ok(Ok(manager)) => {
-             info!("Writer thread finished successfully.");
+             info!("Writer thread finished successfully. {} output files created.", manager.report_files_created());
              Some(manager.report_rows_written())
          },
          Ok(Err(e)) => {
@@ -1553,7 +4216,7 @@ fn main() -> Result<()> {
     info!("-------------------- FINAL SUMMARY --------------------");
     let total_runtime = start_time.elapsed();
     info!("Total execution time: {}", format_elapsed(total_runtime));
-    info!("Total input files found: {}", files_to_process_with_filters.len());
+    info!("Total input files found: {}", successful_files_count + files_with_errors.len());
     info!("Files processed successfully: {}", successful_files_count);
     if !files_with_errors.is_empty() {
         warn!("Files with processing errors: {}", files_with_errors.len());
@@ -1573,7 +4236,10 @@ fn main() -> Result<()> {
          let mut sorted_counts: Vec<_> = counts.into_iter().collect();
          sorted_counts.sort_by_key(|(name, _)| name.clone());
          for (table_name, count) in sorted_counts {
-             info!("  - {}.csv: {}", table_name, count);
+             match cli.format {
+                 OutputFormat::Duckdb => info!("  - {}: {}", table_name, count),
+                 _ => info!("  - {}.{}: {}", table_name, cli.format.file_extension(), count),
+             }
          }
      } else {
          error!("Could not retrieve final row counts from writer thread.");
@@ -1588,4 +4254,590 @@ fn main() -> Result<()> {
      }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_doi_cases() {
+        let cases = [
+            ("10.1000/ABC.def", Some("10.1000/abc.def")),
+            ("  https://doi.org/10.1000/xyz ", Some("10.1000/xyz")),
+            ("http://dx.doi.org/10.1000.5/x", Some("10.1000.5/x")),
+            ("doi: 10.1000/x(1)", Some("10.1000/x(1)")),
+            ("10.1000/é", Some("10.1000/é")),
+            ("10.1000/", None),
+            ("10.1000/a b", None),
+            ("10.abc/x", None),
+            ("11.1000/x", None),
+            ("10..1/x", None),
+            ("10.１０００/x", None),
+            ("", None),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_doi(raw).as_deref(), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn normalize_ror_cases() {
+        let cases = [
+            ("05dxps055", Some("https://ror.org/05dxps055")),
+            ("https://ror.org/05DXPS055/", Some("https://ror.org/05dxps055")),
+            ("ror.org/03yrm5c26", Some("https://ror.org/03yrm5c26")),
+            ("05dxps056", None),
+            ("15dxps055", None),
+            ("05dxps05", None),
+            ("05dups055", None),
+            ("05dxps05é", None),
+            ("0édxps05", None),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_ror(raw).as_deref(), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn normalize_alternate_identifier_cases() {
+        let cases = [
+            (IdentifierKind::Pmid, "https://pubmed.ncbi.nlm.nih.gov/1000/", Some("1000")),
+            (IdentifierKind::Pmid, "PMID:1000", Some("1000")),
+            (IdentifierKind::Pmid, "10a0", None),
+            (IdentifierKind::Pmid, "１０００", None),
+            (IdentifierKind::Pmcid, "https://www.ncbi.nlm.nih.gov/pmc/articles/PMC123/", Some("PMC123")),
+            (IdentifierKind::Pmcid, "pmc123", Some("PMC123")),
+            (IdentifierKind::Pmcid, "123", Some("PMC123")),
+            (IdentifierKind::Pmcid, "PMC", None),
+            (IdentifierKind::Pmcid, "PMC12é", None),
+            (IdentifierKind::Openalex, "https://openalex.org/W2741809807", Some("W2741809807")),
+            (IdentifierKind::Openalex, "w2741809807", Some("W2741809807")),
+            (IdentifierKind::Openalex, "W", None),
+            (IdentifierKind::Openalex, "2741809807", None),
+            (IdentifierKind::Openalex, "é2741809807", None),
+        ];
+        for (kind, raw, expected) in cases {
+            assert_eq!(kind.normalize(raw).as_deref(), expected, "{:?} {}", kind, raw);
+        }
+    }
+
+    #[test]
+    fn normalize_orcid_cases() {
+        let cases = [
+            ("0000-0002-1825-0097", Some("https://orcid.org/0000-0002-1825-0097")),
+            ("http://orcid.org/0000-0002-1825-0097/", Some("https://orcid.org/0000-0002-1825-0097")),
+            ("000000021825009X", None),
+            ("0000-0002-1694-233x", Some("https://orcid.org/0000-0002-1694-233X")),
+            ("0000-0002-1825-0098", None),
+            ("00000-002-1825-0097", None),
+            ("00000000000000é", None),
+            ("0000-0002-1825-009é", None),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(normalize_orcid(raw).as_deref(), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn json_path_cases() {
+        let doc = serde_json::json!({
+            "DOI": "10.1/x",
+            "a/b": 1,
+            "author": [
+                {"name": "A", "affiliation": [{"name": "X"}, {"name": "Y"}]},
+                {"name": "B", "role": "editor", "affiliation": []},
+            ],
+        });
+        let cases: [(&str, &[Value], &[&str]); 10] = [
+            ("DOI", &[Value::from("10.1/x")], &["/DOI"]),
+            ("/DOI", &[Value::from("10.1/x")], &["/DOI"]),
+            ("/a~1b", &[Value::from(1)], &["/a~1b"]),
+            ("/author/1/name", &[Value::from("B")], &["/author/1/name"]),
+            ("/author/*/name", &[Value::from("A"), Value::from("B")], &["/author/0/name", "/author/1/name"]),
+            ("$.author[-1].name", &[Value::from("B")], &["/author/1/name"]),
+            ("$.author[*].affiliation[*].name", &[Value::from("X"), Value::from("Y")], &["/author/0/affiliation/0/name", "/author/0/affiliation/1/name"]),
+            ("$.author[?(@.role == 'editor')].name", &[Value::from("B")], &["/author/1/name"]),
+            ("$.author[?(@.role)]['name']", &[Value::from("B")], &["/author/1/name"]),
+            ("/missing", &[], &[]),
+        ];
+        for (source, expected, pointers) in cases {
+            let path = JsonPath::try_from(source.to_string()).unwrap();
+            assert_eq!(path.values(&doc), expected.iter().collect::<Vec<_>>(), "{}", source);
+            assert_eq!(path.pointers(&doc), pointers, "{}", source);
+        }
+        for malformed in ["$..name", "$.author[", "$.author[?(@.role == )]", "$.author[?(role)]", "$author"] {
+            assert!(compile_path(malformed).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn json_path_items() {
+        let doc = serde_json::json!({"ids": ["a", "b"], "author": [{"id": "x"}, {"id": "y"}]});
+        let path = |p: &str| JsonPath::try_from(p.to_string()).unwrap();
+        assert_eq!(path("/ids").items(&doc, true), Some(vec![&doc["ids"][0], &doc["ids"][1]]));
+        assert_eq!(path("/ids").items(&doc, false), Some(vec![&doc["ids"]]));
+        assert_eq!(path("/author/*/id").items(&doc, false), Some(vec![&doc["author"][0]["id"], &doc["author"][1]["id"]]));
+        assert_eq!(path("/author/0/id").items(&doc, true), Some(Vec::new()));
+        assert_eq!(path("/author/*/name").items(&doc, true), None);
+        assert_eq!(path("/ids").item_pointers("/x", &doc, true, 2), ["/x/ids/0", "/x/ids/1"]);
+        assert_eq!(path("/author/*/id").item_pointers("", &doc, true, 2), ["/author/0/id", "/author/1/id"]);
+        let field = |f: &str| FieldPath::try_from(f.to_string()).unwrap();
+        assert_eq!(field(".").0.first(&doc), Some(&doc));
+        assert_eq!(field("author/1/id").0.first(&doc), Some(&Value::from("y")));
+    }
+
+    #[test]
+    fn filter_condition_matches() {
+        let node = serde_json::json!({"type": "Journal-Article", "year": 2021, "issued": "2021-03-04", "tags": ["a", "b"], "missing": null});
+        let cases = [
+            (r#"{"field": "type", "equals": "Journal-Article"}"#, true),
+            (r#"{"field": "type", "equals": "journal-article"}"#, false),
+            (r#"{"field": "type", "equals": "journal-article", "case_insensitive": true}"#, true),
+            (r#"{"field": "type", "not_equals": "book"}"#, true),
+            (r#"{"field": "year", "equals": 2021}"#, true),
+            (r#"{"field": "tags", "in": ["b", "c"]}"#, false),
+            (r#"{"field": "/tags/*", "in": ["b", "c"]}"#, true),
+            (r#"{"field": "type", "regex": "^Journal-"}"#, true),
+            (r#"{"field": "missing", "exists": true}"#, false),
+            (r#"{"field": "missing", "missing": true}"#, true),
+            (r#"{"field": "absent", "exists": false}"#, true),
+            (r#"{"field": "year", "gte": 2021, "lt": 2022}"#, true),
+            (r#"{"field": "year", "gt": 2021}"#, false),
+            (r#"{"field": "issued", "between": ["2021-01-01", "2021-12-31"]}"#, true),
+            (r#"{"field": "issued", "lt": "2020"}"#, false),
+            (r#"{"field": "type", "any_of": [{"equals": "book"}, {"regex": "Article$"}]}"#, true),
+            (r#"{"all_of": [{"field": "year", "equals": 2021}, {"field": "type", "equals": "book"}]}"#, false),
+            (r#"{"field": "type", "not": {"equals": "book"}}"#, true),
+            (r#"{"not": {"field": "/tags/*", "equals": "a"}}"#, false),
+        ];
+        for (json, expected) in cases {
+            let condition: FilterConditionConfig = serde_json::from_str(json).unwrap();
+            condition.validate(false).unwrap();
+            assert_eq!(condition.matches(&node, None), expected, "{}", json);
+        }
+    }
+
+    #[test]
+    fn profile_rejects_doi_prefix_fallback_without_transform() {
+        let mut profile_json: Value = serde_json::from_str(include_str!("../sample_configs/crossref_profile.json")).unwrap();
+        serde_json::from_value::<Profile>(profile_json.clone()).unwrap().validate().unwrap();
+        let doi_prefix = profile_json["filters"].as_array_mut().unwrap().iter_mut().find(|f| f["cli_arg"] == "doi_prefix").unwrap();
+        doi_prefix.as_object_mut().unwrap().remove("transform");
+        let error = serde_json::from_value::<Profile>(profile_json).unwrap().validate().unwrap_err();
+        assert!(error.to_string().contains("split_before"), "{}", error);
+    }
+
+    #[test]
+    fn record_filter_cases() {
+        let profile_filters: Option<Vec<FilterConfig>> = Some(serde_json::from_str(r#"[
+            {"cli_arg": "member", "path": "/member"},
+            {"cli_arg": "type", "path": "/type"},
+            {"cli_arg": "year", "path": "/issued/date-parts/0/0"},
+            {"cli_arg": "doi_prefix", "path": "/prefix", "fallback_from": "/DOI", "transform": {"type": "split_before", "delimiter": "/"}},
+            {"cli_arg": "doi_lower", "path": "/doi_lower", "fallback_from": "/DOI", "transform": [{"type": "lowercase"}, {"type": "trim"}]},
+            {"cli_arg": "publisher", "path": "/publisher", "fallback_from": "/institution/*/name"}
+        ]"#).unwrap());
+        let records = [
+            serde_json::json!({"member": "78", "type": "journal-article", "issued": {"date-parts": [[2021, 3]]}, "prefix": "10.1016", "DOI": "10.1016/X"}),
+            serde_json::json!({"member": 311, "type": "book-chapter", "issued": {"date-parts": [[2019]]}, "DOI": "10.1002/ABC ", "institution": [{"name": "Uni A"}, {"name": "Uni B"}]}),
+            serde_json::json!({"type": "journal-article", "DOI": "10.5555/z"}),
+        ];
+        let cases: [(&str, [bool; 3]); 15] = [
+            ("{}", [true, true, true]),
+            ("member: 78", [true, false, false]),
+            ("member: '311'", [false, true, false]),
+            ("member: [78, 311]", [true, true, false]),
+            ("type: [journal-article]\nmember: 78", [true, false, false]),
+            ("year: {gte: 2020}", [true, false, false]),
+            ("year: {between: [2019, 2020]}", [false, true, false]),
+            ("member: {missing: true}", [false, false, true]),
+            ("doi_prefix: '10.1002'", [false, true, false]),
+            ("doi_prefix: {regex: '^10\\.(1016|5555)$'}", [true, false, true]),
+            ("doi_lower: 10.1002/abc", [false, true, false]),
+            ("publisher: Uni B", [false, true, false]),
+            ("any_of: [{doi_prefix: '10.1002'}, {member: 78}]", [true, true, false]),
+            ("all_of: [{type: journal-article}, {not: {doi_prefix: '10.1016'}}]", [false, false, true]),
+            ("not: {any_of: [{year: 2021}, {publisher: {in: [Uni A, Uni C]}}]}", [false, false, true]),
+        ];
+        for (yaml, expected) in cases {
+            let task_filters: serde_yaml::Mapping = serde_yaml::from_str(yaml).unwrap();
+            let filter = resolve_task_filters(&profile_filters, &task_filters).unwrap();
+            let matched = records.each_ref().map(|record| filter.matches(record));
+            assert_eq!(matched, expected, "{}", yaml);
+        }
+        for invalid in ["member: {field: type, equals: x}", "member: [[78]]", "member: {}", "any_of: {member: 78}", "not: [member: 78]"] {
+            let task_filters: serde_yaml::Mapping = serde_yaml::from_str(invalid).unwrap();
+            assert!(resolve_task_filters(&profile_filters, &task_filters).is_err(), "{}", invalid);
+        }
+        let unknown: serde_yaml::Mapping = serde_yaml::from_str("journal: x").unwrap();
+        assert!(resolve_task_filters(&profile_filters, &unknown).unwrap().is_empty());
+    }
+
+    #[test]
+    fn value_template_cases() {
+        let node = serde_json::json!({"given": " Ann ", "family": "Lee", "year": 2021, "name": {"suffix": "Jr"}, "empty": ""});
+        let cases = [
+            ("{family}, {given}", Some("Lee, Ann")),
+            ("{given} {family}[, {/name/suffix}]", Some("Ann Lee, Jr")),
+            ("{given}[ {middle}] {family}", Some("Ann Lee")),
+            ("{family} ({year})", Some("Lee (2021)")),
+            ("{family} {middle}", None),
+            ("{empty}", None),
+            ("[{middle}]", None),
+            ("{{{family}}} [[x]]", Some("{Lee} [x]")),
+            ("{$.name.suffix}", Some("Jr")),
+        ];
+        for (template, expected) in cases {
+            let parsed = ValueTemplate::try_from(template.to_string()).unwrap();
+            assert_eq!(parsed.render(&node).as_deref(), expected, "{}", template);
+        }
+        for malformed in ["no fields", "{family", "family}", "{ }", "[{family}", "{family}]", "{$..x}"] {
+            assert!(ValueTemplate::try_from(malformed.to_string()).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn id_template_cases() {
+        let record_json = serde_json::json!({"DOI": " 10.1/x ", "year": 2021, "none": null});
+        let record = RecordContext { record_id: "rec-1", record_json: &record_json, input_file: Path::new("in.jsonl"), line_number: 1 };
+        let vars = |record| IdTemplateVars { value_type: "author", value_content: "Ann Lee", source_id: "src", process_id: "proc", record };
+        let cases = [
+            ("{value_type}:{value_content}", Some(&record), "author:Ann Lee", false),
+            ("{source_id}/{process_id}/{ value_type }:{value_content}", None, "src/proc/author:Ann Lee", false),
+            ("{{{value_type}}}:{value_content}}}", None, "{author}:Ann Lee}", false),
+            ("{record_id}|{value_type}|{value_content}", Some(&record), "rec-1|author|Ann Lee", true),
+            ("{record_id}|{value_type}|{value_content}", None, "|author|Ann Lee", true),
+            ("{record:DOI}|{record:/year}|{record:none}|{record:$.missing}|{value_type}|{value_content}", Some(&record), "10.1/x|2021|||author|Ann Lee", true),
+        ];
+        for (format, record, expected, record_scoped) in cases {
+            let template = IdTemplate::try_from(format.to_string()).unwrap();
+            assert_eq!(template.render(&vars(record)), expected, "{}", format);
+            assert_eq!(template.is_record_scoped(), record_scoped, "{}", format);
+        }
+        for invalid in [
+            "{value_type}",
+            "{value_content}",
+            "{value_type}:{value_contents}",
+            "{value_type}:{value_content}:{record:}",
+            "{value_type}:{value_content}:{record:$..x}",
+            "{value_type}:{value_content",
+            "{value_type}}:{value_content}",
+        ] {
+            assert!(IdTemplate::try_from(invalid.to_string()).is_err(), "{}", invalid);
+        }
+    }
+
+    /// Runs `records` through a processor for `profile_json` and returns everything it emitted.
+    fn process_test_records(profile_json: &Value, records: &[Value]) -> OutputBatch {
+        let profile = Arc::new(serde_json::from_value::<Profile>(profile_json.clone()).unwrap());
+        profile.validate().unwrap();
+        let null_value_ids = Arc::new(precompute_null_value_ids(&[Arc::clone(&profile)]).unwrap());
+        let (batch_sender, _batch_receiver) = bounded(1);
+        let processor = JsonlProcessor::new(profile, null_value_ids, Arc::default(), Arc::default(), Arc::default(),
+            RecordFilter::All(Vec::new()), usize::MAX, batch_sender, RelationshipIdMode::Deterministic, false);
+        let mut batch = OutputBatch::default();
+        let mut stats = FileProcessingStats::default();
+        for (index, record) in records.iter().enumerate() {
+            processor.process_record(record, Path::new("in.jsonl"), index + 1, &mut batch, &mut stats).unwrap();
+        }
+        batch
+    }
+
+    fn test_profile(entities: Value) -> Value {
+        serde_json::json!({
+            "profile_description": "test",
+            "source_info": {"source_id": "src"},
+            "process_info": {"process_id": "proc"},
+            "record_identifier": {"path": "/DOI", "required": true},
+            "deterministic_ids": {"record_prefix": "rec", "value_prefix": "val", "value_format": "{value_type}:{value_content}"},
+            "null_values": {},
+            "filters": null,
+            "entities": entities,
+        })
+    }
+
+    #[test]
+    fn identity_scope_cases() {
+        let records = [
+            serde_json::json!({"DOI": "10.1/a", "author": [
+                {"name": "J. Smith", "affiliation": [{"name": "Uni A"}]},
+                {"name": "J. Smith", "affiliation": [{"name": "Uni A"}, {"name": "Uni B"}]},
+            ]}),
+            serde_json::json!({"DOI": "10.1/b", "author": [{"name": "J. Smith", "affiliation": [{"name": "Uni A"}]}]}),
+        ];
+        // Distinct (record, value) links for authors, distinct affiliation values, same_string_as links.
+        let cases = [
+            ("global", "global", 1, 2, 0),
+            ("record", "global", 2, 2, 2),
+            ("occurrence", "global", 3, 2, 3),
+            ("global", "parent", 1, 2, 2),
+            ("record", "parent", 2, 3, 5),
+            ("occurrence", "parent", 3, 4, 7),
+            ("occurrence", "occurrence", 3, 4, 7),
+        ];
+        for (author_scope, affiliation_scope, author_values, affiliation_values, same_string_links) in cases {
+            let profile = test_profile(serde_json::json!([{
+                "name": "Author", "path": "/author", "is_array": true, "relationship_to_record": "has_author",
+                "value_extraction": {"type": "field", "field": "name", "target_value_type": "author"},
+                "identity_scope": author_scope,
+                "nested_entities": [{
+                    "name": "Affiliation", "path": "/affiliation", "is_array": true, "relationship_to_parent": "has_affiliation",
+                    "value_extraction": {"type": "field", "field": "name", "target_value_type": "affiliation"},
+                    "identity_scope": affiliation_scope,
+                }],
+            }]));
+            let batch = process_test_records(&profile, &records);
+            let label = format!("{} {}", author_scope, affiliation_scope);
+            let author_ids: HashSet<&str> = batch.record_value_relationships.iter().map(|r| r.value_id.as_str()).collect();
+            assert_eq!(author_ids.len(), author_values, "{}", label);
+            let affiliation_ids: HashSet<&str> = batch.value_value_relationships.iter()
+                .filter(|r| r.relationship_type == "has_affiliation")
+                .map(|r| r.target_value_id.as_str())
+                .collect();
+            assert_eq!(affiliation_ids.len(), affiliation_values, "{}", label);
+            let same_string: HashSet<(&str, &str)> = batch.value_value_relationships.iter()
+                .filter(|r| r.relationship_type == SAME_STRING_AS_RELATIONSHIP)
+                .map(|r| (r.source_value_id.as_str(), r.target_value_id.as_str()))
+                .collect();
+            assert_eq!(same_string.len(), same_string_links, "{}", label);
+            // Every scoped value points at the global value with the same type and content.
+            let global_ids: HashSet<String> = ["author:J. Smith", "affiliation:Uni A", "affiliation:Uni B"].iter()
+                .map(|content| generate_deterministic_id("val", content))
+                .collect();
+            for (scoped_id, global_id) in &same_string {
+                assert!(global_ids.contains(*global_id) && !global_ids.contains(*scoped_id), "{}", label);
+            }
+        }
+    }
+
+    #[test]
+    fn record_links_ignore_processing_order() {
+        let mut profile = test_profile(serde_json::json!([]));
+        profile["record_identifier"] = serde_json::json!({
+            "path": "/doi", "required": false, "normalize": "doi",
+            "fallbacks": [{"path": "/id", "type": "openalex", "normalize": "openalex"}],
+        });
+        profile["record_links"] = serde_json::json!([
+            {"relationship_type": "references", "path": "/referenced_works", "type": "openalex", "normalize": "openalex"},
+            {"relationship_type": "cites", "path": "/cites", "normalize": "doi"},
+        ]);
+        let records = [
+            serde_json::json!({"doi": "10.1/a", "id": "https://openalex.org/W1", "referenced_works": ["W2", "W3", "w2", "W1"], "cites": ["https://doi.org/10.1/B"]}),
+            serde_json::json!({"doi": "10.1/b", "id": "W2", "referenced_works": ["W1"], "cites": ["10.1/a", "10.1/c"]}),
+            serde_json::json!({"id": "W3"}),
+        ];
+        let record_id = |content: &str| generate_deterministic_id("rec", content);
+        let mut outputs = Vec::new();
+        for order in [[0, 1, 2], [2, 1, 0], [1, 0, 2]] {
+            let batch = process_test_records(&profile, &order.map(|i| records[i].clone()));
+            let records: HashSet<(String, String, String)> = batch.records.into_iter().map(|r| (r.record_id, r.primary_id, r.primary_id_type)).collect();
+            let mut links: Vec<(String, String, String, i32)> = batch.record_record_relationships.into_iter()
+                .map(|r| (r.source_record_id, r.target_record_id, r.relationship_type, r.ordinal))
+                .collect();
+            links.sort();
+            outputs.push((records, links));
+        }
+        assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]));
+
+        let (records, links) = &outputs[0];
+        let (a, b, c) = (record_id("10.1/a"), record_id("10.1/b"), record_id("10.1/c"));
+        let (w1, w2, w3) = (record_id("openalex:W1"), record_id("openalex:W2"), record_id("openalex:W3"));
+        // W1 and W2 are alternate identifiers of a and b, so links by OpenAlex ID reach stubs; W3 is ingested.
+        let expected_links = [
+            (a.clone(), b.clone(), "cites", 1),
+            (a.clone(), w2.clone(), "references", 1),
+            (a.clone(), w3.clone(), "references", 2),
+            (a.clone(), w1.clone(), "references", 3),
+            (b.clone(), a.clone(), "cites", 1),
+            (b.clone(), c.clone(), "cites", 2),
+            (b.clone(), w1.clone(), "references", 1),
+        ];
+        let mut expected_links: Vec<_> = expected_links.into_iter().map(|(s, t, r, o)| (s, t, r.to_string(), o)).collect();
+        expected_links.sort();
+        assert_eq!(links, &expected_links);
+        let expected_records: HashSet<(String, String, String)> = [
+            (a, "10.1/a", "doi"), (b, "10.1/b", "doi"), (c, "10.1/c", "doi"),
+            (w1, "W1", "openalex"), (w2, "W2", "openalex"), (w3, "W3", "openalex"),
+        ].into_iter().map(|(id, value, id_type)| (id, value.to_string(), id_type.to_string())).collect();
+        assert_eq!(records, &expected_records);
+    }
+
+    #[test]
+    fn value_extraction_types_share_options() {
+        let sources = [
+            r#""type": "field", "field": "name""#,
+            r#""type": "combine_fields", "fields": ["given", "family"], "separator": " ""#,
+            r#""type": "item""#,
+            r#""type": "self""#,
+            r#""type": "coalesce", "fields": ["name", "/alt/0"]"#,
+            r#""type": "template", "template": "{family}, {given}""#,
+        ];
+        for source in sources {
+            let json = format!(r#"{{{}, "target_value_type": "author", "use_null": "none", "confidence_score": 0.5, "transforms": [{{"type": "trim"}}], "split": ";"}}"#, source);
+            let config: ValueExtractionConfig = serde_json::from_str(&json).unwrap();
+            config.validate().unwrap();
+            assert_eq!(config.options.target_value_type, "author", "{}", json);
+            assert_eq!(config.options.use_null.as_deref(), Some("none"), "{}", json);
+            assert_eq!(config.options.confidence_score, Some(0.5), "{}", json);
+            assert_eq!(config.options.transforms, [ValueTransform::Trim], "{}", json);
+            assert!(config.options.split.is_some(), "{}", json);
+        }
+        let single: ValueExtractionConfig = serde_json::from_str(r#"{"type": "field", "field": "name", "target_value_type": "author", "transforms": {"type": "trim"}}"#).unwrap();
+        assert_eq!(single.options.transforms, [ValueTransform::Trim]);
+        let item: ValueExtractionConfig = serde_json::from_str(r#"{"type": "item", "target_value_type": "issn"}"#).unwrap();
+        assert_eq!(item.source, ExtractionSource::Item);
+        assert!(item.options.use_null.is_none() && item.options.transforms.is_empty());
+        assert!(serde_json::from_str::<ValueExtractionConfig>(r#"{"type": "item"}"#).is_err());
+        assert!(serde_json::from_str::<ValueExtractionConfig>(r#"{"type": "field", "target_value_type": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn split_config_cases() {
+        let cases: [(&str, &str, &[&str]); 6] = [
+            (r#"";""#, "Uni A; Uni B;;", &["Uni A", "Uni B"]),
+            (r#"{"delimiter": ";", "trim": false}"#, "Uni A; Uni B", &["Uni A", " Uni B"]),
+            (r#"{"delimiter": ";", "drop_empty": false}"#, "a;;b", &["a", "", "b"]),
+            (r#"{"regex": "\\s*(;|\\band\\b)\\s*"}"#, "Uni A and Uni B; Uni C", &["Uni A", "Uni B", "Uni C"]),
+            (r#"" | ""#, "a | b|c", &["a", "b|c"]),
+            (r#"";""#, " ; ", &[]),
+        ];
+        for (json, text, expected) in cases {
+            let split: SplitConfig = serde_json::from_str(json).unwrap();
+            assert_eq!(split.split(text), expected, "{} {}", json, text);
+        }
+        for invalid in [r#""""#, r#"{}"#, r#"{"delimiter": ";", "regex": ";"}"#, r#"{"regex": "("}"#] {
+            assert!(serde_json::from_str::<SplitConfig>(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn record_value_dedup_policies() {
+        let link = |id: &str, value_id: &str, ordinal: i32, process_id: &str| RecordValueRow {
+            record_value_id: id.to_string(), record_id: "r1".to_string(), value_id: value_id.to_string(),
+            relationship_type: "author".to_string(), ordinal, process_id: process_id.to_string(), timestamp: String::new(),
+        };
+        let provenance = |relationship_id: &str| RelationshipProvenanceRow {
+            relationship_id: relationship_id.to_string(), relationship_table: "record_value_relationships".to_string(),
+            input_file: "in.jsonl".to_string(), line_number: 1, json_pointer: String::new(),
+        };
+        let batches = || [
+            vec![link("a", "v1", 1, "p1"), link("b", "v1", 1, "p1"), link("c", "v1", 2, "p1")],
+            vec![link("d", "v1", 1, "p2"), link("e", "v1", 2, "p1"), link("f", "v2", 1, "p1")],
+        ];
+        let cases: [(RecordValueDedupPolicy, &[&str]); 3] = [
+            (RecordValueDedupPolicy::All, &["a", "b", "c", "d", "e", "f"]),
+            (RecordValueDedupPolicy::Exact, &["a", "c", "d", "f"]),
+            (RecordValueDedupPolicy::IgnoreOrdinal, &["a", "d", "f"]),
+        ];
+        for (policy, expected) in cases {
+            for provenance_enabled in [false, true] {
+                let tracker = WrittenRowTracker::new(policy, provenance_enabled);
+                let mut kept = Vec::new();
+                let mut provenance_ids = Vec::new();
+                for rows in batches() {
+                    let relationship_provenance = rows.iter().map(|row| provenance(&row.record_value_id)).collect();
+                    let batch = tracker.retain_unwritten(OutputBatch { record_value_relationships: rows, relationship_provenance, ..Default::default() });
+                    kept.extend(batch.record_value_relationships.into_iter().map(|row| row.record_value_id));
+                    provenance_ids.extend(batch.relationship_provenance.into_iter().map(|row| row.relationship_id));
+                }
+                assert_eq!(kept, expected, "{:?}", policy);
+                // With provenance every assertion keeps a row, pointing at the link that was written.
+                let expected_provenance: &[&str] = match (provenance_enabled, policy) {
+                    (false, _) | (true, RecordValueDedupPolicy::All) => &["a", "b", "c", "d", "e", "f"],
+                    (true, RecordValueDedupPolicy::Exact) => &["a", "a", "c", "d", "c", "f"],
+                    (true, RecordValueDedupPolicy::IgnoreOrdinal) => &["a", "a", "a", "d", "a", "f"],
+                };
+                assert_eq!(provenance_ids, expected_provenance, "{:?} {}", policy, provenance_enabled);
+            }
+        }
+    }
+
+    #[test]
+    fn input_codec_detection() {
+        let gzip: &[u8] = &[0x1f, 0x8b, 0x08, 0x00];
+        let zstd: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd, 0x04];
+        let bzip2: &[u8] = b"BZh91AY&SY";
+        let plain: &[u8] = b"{\"DOI\": \"10.1/x\"}\n";
+        let cases = [
+            ("a.jsonl", gzip, InputCodec::Gzip),
+            ("a.jsonl", zstd, InputCodec::Zstd),
+            ("a.jsonl", bzip2, InputCodec::Bzip2),
+            ("a.jsonl.gz", zstd, InputCodec::Zstd),
+            ("a.jsonl.zst", bzip2, InputCodec::Bzip2),
+            ("a.jsonl.GZ", plain, InputCodec::Gzip),
+            ("a.jsonl.zstd", plain, InputCodec::Zstd),
+            ("a.jsonl.bz2", b"", InputCodec::Bzip2),
+            ("a.jsonl", plain, InputCodec::Plain),
+            ("a.part-0001", plain, InputCodec::Plain),
+            ("a", b"", InputCodec::Plain),
+        ];
+        for (path, header, expected) in cases {
+            assert_eq!(InputCodec::detect(Path::new(path), header), expected, "{} {:?}", path, header);
+        }
+
+        let read_all = |mut reader: Box<dyn Read>| {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).unwrap();
+            bytes
+        };
+        let encoded = [
+            read_all(Box::new(flate2::read::GzEncoder::new(plain, flate2::Compression::default()))),
+            zstd::encode_all(plain, 0).unwrap(),
+            read_all(Box::new(bzip2::read::BzEncoder::new(plain, bzip2::Compression::default()))),
+            plain.to_vec(),
+        ];
+        for bytes in encoded {
+            let mut text = String::new();
+            open_input_reader(bytes.as_slice(), Path::new("a.unknown")).unwrap().read_to_string(&mut text).unwrap();
+            assert_eq!(text.as_bytes(), plain);
+        }
+    }
+
+    #[test]
+    fn record_array_streaming() {
+        let stream = |document: &str, array_path: &str| {
+            let mut records = Vec::new();
+            let found = stream_record_array(document.as_bytes(), array_path, |position, record| {
+                records.push((position, record));
+                Ok(())
+            });
+            (found.map_err(|e| e.to_string()), records)
+        };
+        let first = serde_json::json!({"DOI": "10.1/a", "items": [1]});
+        let second = serde_json::json!({"DOI": "10.1/b"});
+
+        let (found, records) = stream(r#"{"meta":1,"items":[{"DOI":"10.1/a","items":[1]},{"DOI":"10.1/b"}],"tail":[]}"#, "/items");
+        assert_eq!(found, Ok(true));
+        assert_eq!(records, [(1, first.clone()), (2, second.clone())]);
+
+        let (found, records) = stream(r#"[{"DOI":"10.1/a","items":[1]},{"DOI":"10.1/b"}]"#, "");
+        assert_eq!(found, Ok(true));
+        assert_eq!(records, [(1, first.clone()), (2, second.clone())]);
+
+        let (found, records) = stream(r#"{"data":{"items":[{"DOI":"10.1/b"}]}}"#, "/data/items");
+        assert_eq!(found, Ok(true));
+        assert_eq!(records, [(1, second.clone())]);
+
+        for (document, array_path) in [(r#"{"meta":1,"items":{"DOI":"10.1/a"}}"#, "/items"), (r#"{"meta":1}"#, "/items"), ("[]", "/items")] {
+            assert_eq!(stream(document, array_path), (Ok(false), Vec::new()), "{}", document);
+        }
+
+        let (found, records) = stream(r#"{"meta":1,"items":[{"DOI":"10.1/a","items":[1]},{"DOI":"10.1/b"},{"DOI":"#, "/items");
+        assert!(found.is_err());
+        assert_eq!(records, [(1, first), (2, second)]);
+        let (found, _) = stream(r#"{"items":[]} trailing"#, "/items");
+        assert!(found.is_err());
+    }
+
+    #[test]
+    fn filter_condition_rejects_unknown_keys_and_empty_conditions() {
+        assert!(serde_json::from_str::<FilterConditionConfig>(r#"{"field": "type", "equal": "x"}"#).is_err());
+        for json in [r#"{}"#, r#"{"field": "type"}"#, r#"{"all_of": [{"field": "type"}]}"#] {
+            let condition: FilterConditionConfig = serde_json::from_str(json).unwrap();
+            assert!(condition.validate(false).is_err(), "{}", json);
+        }
+        let condition: FilterConditionConfig = serde_json::from_str(r#"{"field": "type", "equals": "x"}"#).unwrap();
+        assert!(condition.validate(false).is_ok());
+    }
+}
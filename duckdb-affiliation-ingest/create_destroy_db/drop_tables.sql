@@ -3,9 +3,13 @@ DROP TABLE IF EXISTS process_record_relationships;
 DROP TABLE IF EXISTS process_value_relationships;
 DROP TABLE IF EXISTS record_value_relationships;
 DROP TABLE IF EXISTS value_value_relationships;
+DROP TABLE IF EXISTS record_record_relationships;
+DROP TABLE IF EXISTS relationship_provenance;
 
 -- Drop main data tables
 DROP TABLE IF EXISTS records;
+DROP TABLE IF EXISTS alternate_identifiers;
+DROP TABLE IF EXISTS record_attributes;
 DROP TABLE IF EXISTS values;
 
 -- Drop optional metadata tables
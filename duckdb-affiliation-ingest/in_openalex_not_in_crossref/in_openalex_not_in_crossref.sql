@@ -95,12 +95,12 @@ COPY (
     )
 
     SELECT DISTINCT
-        rec.doi,
+        rec.primary_id AS doi,
         oa_affil.author_name,
         oa_affil.affiliation_value_content AS affiliation,
         oa_ror.ror_value_content           AS ror_id
     FROM ConflictedLinks cl
-    JOIN records rec ON cl.record_id = rec.record_id
+    JOIN records rec ON cl.record_id = rec.record_id AND rec.primary_id_type = 'doi'
     CROSS JOIN Constants c
     JOIN AffiliationLinkAssertions oa_affil
          ON cl.record_id = oa_affil.record_id